@@ -0,0 +1,125 @@
+use crate::utils::SeededRng;
+use std::collections::HashMap;
+
+// Locality-sensitive hashing index using random hyperplanes (SimHash).
+// Each vector is reduced to one bit per hyperplane (which side it lies on), and
+// vectors with the same code share a bucket. Queries scan their own bucket plus
+// every bucket one bit away, so fewer hyperplanes mean larger buckets (higher
+// recall, slower queries) and more hyperplanes mean smaller buckets (faster
+// queries, lower recall).
+#[derive(Debug, Clone)]
+pub struct LshIndex {
+    hyperplanes: Vec<Vec<f32>>,
+    buckets: HashMap<u64, Vec<usize>>,
+    seed: u64,
+}
+
+impl LshIndex {
+    // Create an empty index; hyperplanes are drawn deterministically from the seed
+    pub fn new(dimension: usize, num_hyperplanes: usize, seed: u64) -> Self {
+        // Codes are stored in a u64
+        let num_hyperplanes = num_hyperplanes.clamp(1, 64);
+        let mut rng = SeededRng::new(seed);
+
+        // Gaussian components (Box-Muller) make the hyperplane directions uniform
+        let hyperplanes = (0..num_hyperplanes)
+            .map(|_| {
+                (0..dimension)
+                    .map(|_| {
+                        let u1 = rng.next_f32().max(f32::MIN_POSITIVE);
+                        let u2 = rng.next_f32();
+                        (-2.0 * u1.ln()).sqrt() * (2.0 * std::f32::consts::PI * u2).cos()
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Self {
+            hyperplanes,
+            buckets: HashMap::new(),
+            seed,
+        }
+    }
+
+    // Build an index over vectors, using their positions as ids
+    pub fn build(vectors: &[Vec<f32>], num_hyperplanes: usize, seed: u64) -> Self {
+        let dimension = vectors.first().map_or(0, |vec| vec.len());
+        let mut index = Self::new(dimension, num_hyperplanes, seed);
+        for (id, vec) in vectors.iter().enumerate() {
+            index.insert(id, vec);
+        }
+        index
+    }
+
+    pub fn insert(&mut self, id: usize, vec: &[f32]) {
+        let code = self.code(vec);
+        self.buckets.entry(code).or_default().push(id);
+    }
+
+    // Binary code: bit i is set when the vector lies on the positive side of hyperplane i
+    pub fn code(&self, vec: &[f32]) -> u64 {
+        self.hyperplanes
+            .iter()
+            .enumerate()
+            .fold(0u64, |code, (bit, plane)| {
+                let dot: f32 = plane.iter().zip(vec).map(|(p, v)| p * v).sum();
+                if dot >= 0.0 {
+                    code | (1 << bit)
+                } else {
+                    code
+                }
+            })
+    }
+
+    // Ids in the query's bucket and in all buckets at Hamming distance 1
+    pub fn candidates(&self, query: &[f32]) -> Vec<usize> {
+        let code = self.code(query);
+        let probes = std::iter::once(code)
+            .chain((0..self.hyperplanes.len()).map(|bit| code ^ (1 << bit)));
+
+        let mut candidates: Vec<usize> = probes
+            .filter_map(|probe| self.buckets.get(&probe))
+            .flatten()
+            .copied()
+            .collect();
+        candidates.sort_unstable();
+        candidates
+    }
+
+    pub fn num_hyperplanes(&self) -> usize {
+        self.hyperplanes.len()
+    }
+
+    pub fn dimension(&self) -> usize {
+        self.hyperplanes.first().map_or(0, |plane| plane.len())
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_similar_vectors_share_candidates() {
+        let vectors = vec![
+            vec![1.0, 0.1, 0.0, 0.0],
+            vec![0.9, 0.2, 0.0, 0.1],
+            vec![-1.0, 0.0, 0.3, -0.2],
+        ];
+        let index = LshIndex::build(&vectors, 8, 42);
+        assert_eq!(index.num_hyperplanes(), 8);
+        assert_eq!(index.dimension(), 4);
+
+        let candidates = index.candidates(&[1.0, 0.15, 0.0, 0.05]);
+        assert!(candidates.contains(&0));
+        assert!(!candidates.contains(&2));
+
+        // Same seed, same hyperplanes
+        let rebuilt = LshIndex::build(&vectors, 8, 42);
+        assert_eq!(index.code(&vectors[2]), rebuilt.code(&vectors[2]));
+    }
+}
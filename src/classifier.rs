@@ -0,0 +1,108 @@
+use crate::incremental::{create_error, IncrementalEmbedder};
+use crate::utils::cosine_similarity;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::incremental::JsValue;
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::JsValue;
+
+// Collects labeled groups of stored document indices for a nearest-centroid classifier
+#[derive(Debug, Clone, Default)]
+pub struct ClassifierBuilder {
+    classes: Vec<(String, Vec<usize>)>,
+}
+
+impl ClassifierBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    // Indices refer to the embedder's stored documents (see IncrementalEmbedder::documents)
+    pub fn class(mut self, label: &str, indices: Vec<usize>) -> Self {
+        self.classes.push((label.to_string(), indices));
+        self
+    }
+    
+    // Compute one centroid per class from the embedder's cached embeddings
+    pub fn build(self, embedder: &IncrementalEmbedder) -> Result<NearestCentroidClassifier<'_>, JsValue> {
+        if self.classes.is_empty() {
+            return Err(create_error("Classifier needs at least one class"));
+        }
+        
+        let mut labels = Vec::with_capacity(self.classes.len());
+        let mut centroids = Vec::with_capacity(self.classes.len());
+        for (label, indices) in self.classes {
+            centroids.push(embedder.centroid(&indices)?);
+            labels.push(label);
+        }
+        
+        Ok(NearestCentroidClassifier {
+            embedder,
+            labels,
+            centroids,
+        })
+    }
+}
+
+// Assigns a text the label of the class centroid most similar to its embedding.
+// Centroids are computed once, so rebuild the classifier after a retrain.
+pub struct NearestCentroidClassifier<'a> {
+    embedder: &'a IncrementalEmbedder,
+    labels: Vec<String>,
+    centroids: Vec<Vec<f32>>,
+}
+
+impl NearestCentroidClassifier<'_> {
+    // The nearest class label and its cosine similarity
+    pub fn classify(&self, text: &str) -> Result<(String, f32), JsValue> {
+        let embedding = self.embedder.transform(text)?;
+        
+        let (best, score) = self.centroids
+            .iter()
+            .map(|centroid| cosine_similarity(&embedding, centroid))
+            .enumerate()
+            .fold((0, f32::NEG_INFINITY), |best, (idx, score)| {
+                if score > best.1 { (idx, score) } else { best }
+            });
+        
+        Ok((self.labels[best].clone(), score))
+    }
+    
+    pub fn labels(&self) -> &[String] {
+        &self.labels
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nearest_centroid_classification() {
+        let mut embedder = IncrementalEmbedder::new(2.0);
+        for text in [
+            "今日は天気がいいですね",
+            "明日の天気は雨です",
+            "天気予報では晴れです",
+            "昨日は映画を見ました",
+            "週末に映画館へ行きます",
+            "新作の映画が面白い",
+        ] {
+            embedder.add_document(text.to_string(), 8).unwrap();
+        }
+        embedder.retrain_blocking(8, |_| {}).unwrap();
+        
+        let classifier = ClassifierBuilder::new()
+            .class("weather", vec![0, 1, 2])
+            .class("movie", vec![3, 4, 5])
+            .build(&embedder)
+            .unwrap();
+        assert_eq!(classifier.labels(), ["weather", "movie"]);
+        
+        let (label, score) = classifier.classify("明後日の天気は晴れ").unwrap();
+        assert_eq!(label, "weather");
+        assert!(score > 0.0);
+        assert_eq!(classifier.classify("映画を見に行きたい").unwrap().0, "movie");
+        
+        assert!(ClassifierBuilder::new().class("empty", vec![10]).build(&embedder).is_err());
+    }
+}
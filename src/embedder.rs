@@ -0,0 +1,70 @@
+use crate::incremental::IncrementalEmbedder;
+use crate::stable_hash::StableHashEmbedder;
+use crate::utils::cosine_similarity;
+
+// Common interface of the embedders, so search and clustering helpers can accept
+// either implementation
+pub trait Embedder {
+    fn transform(&self, text: &str) -> Vec<f32>;
+
+    fn dimension(&self) -> usize;
+
+    fn similarity(&self, text1: &str, text2: &str) -> f32 {
+        cosine_similarity(&self.transform(text1), &self.transform(text2))
+    }
+}
+
+impl Embedder for IncrementalEmbedder {
+    // Failed transforms yield a zero vector
+    fn transform(&self, text: &str) -> Vec<f32> {
+        IncrementalEmbedder::transform(self, text).unwrap_or_else(|_| vec![0.0; self.get_embedding_dim()])
+    }
+
+    fn dimension(&self) -> usize {
+        self.get_embedding_dim()
+    }
+}
+
+impl Embedder for StableHashEmbedder {
+    fn transform(&self, text: &str) -> Vec<f32> {
+        StableHashEmbedder::transform(self, text)
+    }
+
+    fn dimension(&self) -> usize {
+        self.get_dimension()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn most_similar(embedder: &dyn Embedder, query: &str, candidates: &[&str]) -> usize {
+        candidates
+            .iter()
+            .map(|candidate| embedder.similarity(query, candidate))
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(idx, _)| idx)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_generic_over_embedders() {
+        let candidates = ["明日は雨が降りそうです", "今日は天気がいいですね"];
+
+        let mut incremental = IncrementalEmbedder::new(2.0);
+        for doc in candidates {
+            incremental.add_document(doc.to_string(), 16).unwrap();
+        }
+        incremental.add_document("今日は映画を見ました".to_string(), 16).unwrap();
+        incremental.retrain_blocking(16, |_| {}).unwrap();
+        let stable = StableHashEmbedder::new(128, 2);
+
+        let embedders: [&dyn Embedder; 2] = [&incremental, &stable];
+        for embedder in embedders {
+            assert_eq!(embedder.transform("今日は天気").len(), embedder.dimension());
+            assert_eq!(most_similar(embedder, "今日は天気がいい", &candidates), 1);
+        }
+    }
+}
@@ -1,26 +1,83 @@
 use crate::tokenizer::{JapaneseTokenizer, DictionaryEntry};
-use crate::tfidf_lsa::TfIdfLsa;
-use crate::utils::{cosine_similarity, l2_normalize};
+use crate::tfidf_lsa::{TfIdfLsa, TfIdfLsaConfig};
+use crate::stable_hash::StableHashEmbedder;
+use crate::ann::LshIndex;
+use crate::utils::{cosine_similarity, default_format_version, fnv1a_64, is_zero_vector, l2_normalize, normalize, read_format_version, Normalization, SeededRng, FORMAT_VERSION};
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::collections::HashSet;
+use std::sync::{Arc, OnceLock};
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
 
 // Define error type for non-wasm targets
 #[cfg(not(target_arch = "wasm32"))]
-type JsValue = String;
+pub(crate) type JsValue = String;
 
 // Helper function to create errors for both WASM and non-WASM targets
 #[cfg(target_arch = "wasm32")]
-fn create_error(msg: &str) -> JsValue {
+pub(crate) fn create_error(msg: &str) -> JsValue {
     JsValue::from_str(msg)
 }
 
 #[cfg(not(target_arch = "wasm32"))]
-fn create_error(msg: &str) -> JsValue {
+pub(crate) fn create_error(msg: &str) -> JsValue {
     msg.to_string()
 }
 
+// What transform returns when a text shares no tokens with the vocabulary
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum OovStrategy {
+    // Keep the all-zero LSA embedding
+    #[default]
+    ZeroVector,
+    // Fall back to a StableHashEmbedder embedding of the same dimension
+    HashFallback,
+}
+
+// Built-in text cleanup applied before tokenization. Unlike set_preprocessor's
+// closures these are available from JS and survive export/import.
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum Preprocessor {
+    #[default]
+    None,
+    // Lowercase Latin letters (ASCII and fullwidth) so "ABC" and "abc" match
+    Lowercase,
+    // Replace brackets such as 「」【】() with spaces so labels like 【速報】
+    // don't glue onto neighbouring words
+    RemoveBrackets,
+}
+
+impl Preprocessor {
+    fn apply<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        match self {
+            Preprocessor::None => Cow::Borrowed(text),
+            Preprocessor::Lowercase => Cow::Owned(text.to_lowercase()),
+            Preprocessor::RemoveBrackets => Cow::Owned(
+                text.chars()
+                    .map(|ch| if "()[]{}（）［］｛｝「」『』【】〈〉《》〔〕".contains(ch) { ' ' } else { ch })
+                    .collect(),
+            ),
+        }
+    }
+}
+
+// Custom text cleanup registered with set_preprocessor
+type PreprocessorFn = Arc<dyn Fn(&str) -> String + Send + Sync>;
+
+// Why transform produced the vector it did (see diagnose_transform)
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TransformDiagnosis {
+    pub total_tokens: usize,
+    pub in_vocabulary_tokens: usize,
+    pub lsa_active: bool,
+    // Norm of the model's output before the embedder's normalization
+    pub pre_normalization_norm: f32,
+}
+
 #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
 #[derive(Clone, Serialize, Deserialize)]
 pub struct IncrementalEmbedder {
@@ -42,8 +99,105 @@ pub struct IncrementalEmbedder {
     searchable_documents: Vec<String>,
     searchable_vectors: Vec<Vec<f32>>,
     searchable_set: HashSet<String>,
+    
+    // Cached embeddings of all stored documents (rebuilt after retrain and import)
+    #[serde(skip)]
+    document_vectors: Vec<Vec<f32>>,
+    
+    // Optional LSH index over document_vectors for approximate search
+    #[serde(skip)]
+    ann_index: Option<LshIndex>,
+    
+    // Document length limits in characters (0 disables the limit)
+    #[serde(default)]
+    min_doc_chars: usize,
+    #[serde(default)]
+    max_doc_chars: usize,
+    
+    // Set when tokenizer settings change so stored documents are re-tokenized on retrain
+    #[serde(default)]
+    tokenizer_changed: bool,
+    
+    #[serde(default)]
+    oov_strategy: OovStrategy,
+    
+    // TF-IDF multiplier for dictionary terms, applied on the next retrain
+    #[serde(default = "default_dictionary_boost")]
+    dictionary_boost: f32,
+    
+    // Fold each new training document into the current model immediately
+    #[serde(default)]
+    streaming: bool,
+    
+    // Also retrain after this many changes, regardless of update_threshold
+    #[serde(default)]
+    retrain_every: Option<usize>,
+    
+    // Start a background retrain automatically when one becomes due
+    #[serde(default = "default_auto_retrain")]
+    auto_retrain: bool,
+    
+    // When false, training documents keep only their tokens; `documents` stays
+    // empty and `document_set` holds text hashes instead of the texts
+    #[serde(default = "default_store_raw_text")]
+    store_raw_text: bool,
+    
+    #[serde(default)]
+    normalization: Normalization,
+    
+    // A paused retrain keeps its step and pending model until resumed
+    #[serde(default)]
+    retrain_paused: bool,
+    
+    // Retrains keep the vocabulary index of every surviving term
+    #[serde(default)]
+    stable_vocabulary: bool,
+    
+    // Queries shorter than this many characters get extra kanji signal (0 disables)
+    #[serde(default = "default_short_query_chars")]
+    short_query_chars: usize,
+    
+    // Layout version of the export, checked by import_model
+    #[serde(default = "default_format_version")]
+    format_version: u32,
+    
+    // Applied to text before tokenization: the built-in first, then the custom one
+    #[serde(default)]
+    preprocessor: Preprocessor,
+    // Closures can't be serialized; an imported embedder needs set_preprocessor again
+    #[serde(skip)]
+    custom_preprocessor: Option<PreprocessorFn>,
+    
+    // HashFallback embedder, built on first use and reset when the model or the
+    // normalization changes
+    #[serde(skip)]
+    hash_fallback: OnceLock<StableHashEmbedder>,
+}
+
+// document_set entry used when raw text isn't stored
+fn hash_document_text(text: &str) -> String {
+    format!("{:016x}", fnv1a_64(&[text.as_bytes()]))
+}
+
+fn default_dictionary_boost() -> f32 {
+    1.0
+}
+
+fn default_auto_retrain() -> bool {
+    true
+}
+
+fn default_store_raw_text() -> bool {
+    true
+}
+
+fn default_short_query_chars() -> usize {
+    2
 }
 
+// How many times each kanji of a short query is repeated in its tokens
+const SHORT_QUERY_KANJI_BOOST: usize = 3;
+
 #[derive(Clone, Serialize, Deserialize)]
 enum RetrainStep {
     Idle,
@@ -57,94 +211,311 @@ enum RetrainStep {
 impl IncrementalEmbedder {
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen(constructor))]
     pub fn new(update_threshold: f32) -> Self {
-        Self {
-            tokenizer: JapaneseTokenizer::new(),
-            model: TfIdfLsa::new(64),
-            documents: Vec::new(),
-            tokenized_documents: Vec::new(),
-            document_set: HashSet::new(),
-            update_threshold,
-            changes_since_update: 0,
-            is_retraining: false,
-            retrain_progress: 0.0,
-            pending_model: None,
-            retrain_step: RetrainStep::Idle,
-            searchable_documents: Vec::new(),
-            searchable_vectors: Vec::new(),
-            searchable_set: HashSet::new(),
-        }
+        Self::with_tokenizer(update_threshold, JapaneseTokenizer::new())
     }
 
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
     pub fn new_with_ngrams(update_threshold: f32, min_ngram: usize, max_ngram: usize) -> Self {
-        Self {
-            tokenizer: JapaneseTokenizer::new_with_ngrams(min_ngram, max_ngram),
-            model: TfIdfLsa::new(64),
-            documents: Vec::new(),
-            tokenized_documents: Vec::new(),
-            document_set: HashSet::new(),
-            update_threshold,
-            changes_since_update: 0,
-            is_retraining: false,
-            retrain_progress: 0.0,
-            pending_model: None,
-            retrain_step: RetrainStep::Idle,
-            searchable_documents: Vec::new(),
-            searchable_vectors: Vec::new(),
-            searchable_set: HashSet::new(),
-        }
+        Self::with_tokenizer(update_threshold, JapaneseTokenizer::new_with_ngrams(min_ngram, max_ngram))
     }
 
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
     pub fn add_document_for_training(&mut self, text: String, embedding_dim: usize) -> Result<(), JsValue> {
+        // Skip documents outside the configured length range
+        if !self.is_within_length_limits(&text) {
+            return Ok(());
+        }
+        
         // Check if document already exists
-        if self.document_set.contains(&text) {
+        if self.document_set.contains(&self.document_key(&text)) {
             // Document already exists, skip adding
             return Ok(());
         }
         
-        // Add document to collection (training only)
-        self.document_set.insert(text.clone());
-        self.documents.push(text.clone());
-        let tokens = self.tokenizer.tokenize(&text);
-        self.tokenized_documents.push(tokens);
+        let tokens = self.tokenize(&text);
+        self.store_training_document(text, tokens);
+        self.trigger_auto_retrain(embedding_dim)
+    }
+
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn add_document(&mut self, text: String, embedding_dim: usize) -> Result<(), JsValue> {
+        // Skip documents outside the configured length range
+        if !self.is_within_length_limits(&text) {
+            return Ok(());
+        }
         
-        self.changes_since_update += 1;
+        // First add as training document
+        self.add_document_for_training(text.clone(), embedding_dim)?;
         
-        // Check if we need to retrain
-        let change_ratio = self.changes_since_update as f32 / self.documents.len().max(1) as f32;
-        if change_ratio >= self.update_threshold && !self.is_retraining {
-            self.start_background_retrain(embedding_dim)?;
+        // Then add as searchable if not already present
+        self.add_searchable_document(text)
+    }
+    
+    // Bulk add_document: every document is stored before the retrain trigger is
+    // evaluated, once, so a large import starts at most one retrain
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn add_documents(&mut self, texts: Vec<String>, embedding_dim: usize) -> Result<(), JsValue> {
+        for text in texts {
+            if !self.is_within_length_limits(&text) {
+                continue;
+            }
+            
+            if !self.document_set.contains(&self.document_key(&text)) {
+                let tokens = self.tokenize(&text);
+                self.store_training_document(text.clone(), tokens);
+            }
+            self.add_searchable_document(text)?;
         }
         
-        Ok(())
+        self.trigger_auto_retrain(embedding_dim)
     }
     
+    // add_documents with one document per non-empty line (lines are trimmed)
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
-    pub fn add_document(&mut self, text: String, embedding_dim: usize) -> Result<(), JsValue> {
-        // First add as training document
-        self.add_document_for_training(text.clone(), embedding_dim)?;
+    pub fn add_documents_from_lines(&mut self, text: &str, embedding_dim: usize) -> Result<(), JsValue> {
+        let lines: Vec<String> = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect();
+        self.add_documents(lines, embedding_dim)
+    }
+
+    // Append another embedder's training documents (skipping duplicates). The
+    // tokenizers must be compatible; the documents are re-tokenized with this
+    // embedder's dictionary and preprocessors and go through its length limits. A
+    // retrain is needed to build the unified model.
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn merge(&mut self, other: &IncrementalEmbedder) -> Result<(), JsValue> {
+        if !self.tokenizer.is_compatible_with(&other.tokenizer) {
+            return Err(create_error("Cannot merge embedders with incompatible tokenizer settings"));
+        }
         
-        // Then add as searchable if not already present
-        if !self.searchable_set.contains(&text) {
-            self.searchable_set.insert(text.clone());
-            self.searchable_documents.push(text.clone());
+        if !other.store_raw_text && !other.tokenized_documents.is_empty() {
+            return Err(create_error("Cannot merge an embedder that does not store raw document text"));
+        }
+        
+        for text in &other.documents {
+            if !self.is_within_length_limits(text) || self.document_set.contains(&self.document_key(text)) {
+                continue;
+            }
             
-            // Pre-compute and store the vector
-            let vector = self.transform(&text)?;
-            self.searchable_vectors.push(vector);
+            let tokens = self.tokenize(text);
+            self.store_training_document(text.clone(), tokens);
         }
         
         Ok(())
     }
 
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn set_min_doc_chars(&mut self, min_chars: usize) {
+        self.min_doc_chars = min_chars;
+    }
+    
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn set_max_doc_chars(&mut self, max_chars: usize) {
+        self.max_doc_chars = max_chars;
+    }
+    
+    // Empty or whitespace-only text has no features, so it explicitly maps to the
+    // all-zero vector (see `utils::is_zero_vector`), whose similarity to anything is 0.0.
+    // The short-query kanji boost (see set_short_query_chars) is query-only: stored
+    // document vectors are built without it, so transform of a one-character
+    // document's text can differ from that document's stored vector.
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
     pub fn transform(&self, text: &str) -> Result<Vec<f32>, JsValue> {
-        let tokens = self.tokenizer.tokenize(text);
-        let mut embedding = self.model.transform(&tokens);
-        l2_normalize(&mut embedding);
+        if text.trim().is_empty() {
+            return Ok(vec![0.0; self.model.embedding_dim()]);
+        }
+        
+        let mut embedding = self.model.transform(&self.query_tokens(text));
+        self.pad_to_embedding_dim(&mut embedding);
+        if embedding.len() != self.get_embedding_dim() {
+            return Err(create_error(&format!(
+                "Model produced a {}-dimensional embedding, expected {}",
+                embedding.len(),
+                self.get_embedding_dim()
+            )));
+        }
+        
+        // Out-of-vocabulary text: hashed n-grams keep the query from being all zeros,
+        // at the cost of not living in the learned LSA space
+        if self.oov_strategy == OovStrategy::HashFallback && is_zero_vector(&embedding) {
+            return Ok(self.hash_fallback_embedding(text));
+        }
+        
+        normalize(&mut embedding, self.normalization);
         Ok(embedding)
     }
+    
+    // Embed each sentence (split after 。！？) and return the normalized mean, so
+    // every topic of a long, multi-topic document is represented instead of only
+    // its dominant vocabulary. Text without terminators embeds as with transform.
+    // HashFallback applies to the whole text, only when no sentence has a known term.
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn transform_pooled(&self, text: &str) -> Result<Vec<f32>, JsValue> {
+        let sentences: Vec<&str> = text
+            .split_inclusive(['。', '！', '？'])
+            .filter(|sentence| !sentence.trim().is_empty())
+            .collect();
+        if sentences.len() <= 1 {
+            return self.transform(text);
+        }
+        
+        let mut pooled = vec![0.0; self.get_embedding_dim()];
+        for sentence in sentences {
+            let embedding = self.embed_tokens(&self.query_tokens(sentence));
+            if embedding.len() != pooled.len() {
+                return Err(create_error(&format!(
+                    "Model produced a {}-dimensional embedding, expected {}",
+                    embedding.len(),
+                    pooled.len()
+                )));
+            }
+            for (sum, value) in pooled.iter_mut().zip(embedding) {
+                *sum += value;
+            }
+        }
+        
+        if self.oov_strategy == OovStrategy::HashFallback && is_zero_vector(&pooled) {
+            return Ok(self.hash_fallback_embedding(text));
+        }
+        normalize(&mut pooled, self.normalization);
+        Ok(pooled)
+    }
+    
+    // Token counts, LSA state and raw norm behind transform(text), to explain
+    // all-zero embeddings: no tokens, no in-vocabulary tokens, or no model yet
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn diagnose_transform(&self, text: &str) -> TransformDiagnosis {
+        let tokens = if text.trim().is_empty() { Vec::new() } else { self.query_tokens(text) };
+        let vocabulary = self.model.vocabulary();
+        let embedding = self.model.transform(&tokens);
+        
+        TransformDiagnosis {
+            total_tokens: tokens.len(),
+            in_vocabulary_tokens: tokens.iter().filter(|token| vocabulary.contains_key(*token)).count(),
+            lsa_active: self.model.is_lsa_active(),
+            pre_normalization_norm: embedding.iter().map(|x| x * x).sum::<f32>().sqrt(),
+        }
+    }
+    
+    // Fraction of the text's tokens that are in the model's vocabulary (0.0 for text
+    // without tokens). Values near 0 explain weak or all-zero embeddings.
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn vocabulary_coverage(&self, text: &str) -> f32 {
+        let tokens = self.query_tokens(text);
+        if tokens.is_empty() {
+            return 0.0;
+        }
+        
+        let vocabulary = self.model.vocabulary();
+        let known = tokens.iter().filter(|token| vocabulary.contains_key(*token)).count();
+        known as f32 / tokens.len() as f32
+    }
+    
+    // The text's in-vocabulary terms followed by, for each of them, the n terms
+    // with the most similar LSA loadings (see TfIdfLsa::similar_terms). Terms that
+    // co-occur in the corpus end up close, which makes this a cheap synonym and
+    // related-term expansion for recall. No expansion before LSA is active.
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn expand_query(&self, text: &str, n: usize) -> Vec<String> {
+        let vocabulary = self.model.vocabulary();
+        let mut terms = self.tokenize(text);
+        terms.dedup();
+        terms.retain(|term| vocabulary.contains_key(term));
+        
+        let mut seen: HashSet<String> = terms.iter().cloned().collect();
+        let mut expansions = Vec::new();
+        for term in &terms {
+            for (similar, _) in self.model.similar_terms(term, n) {
+                if seen.insert(similar.clone()) {
+                    expansions.push(similar);
+                }
+            }
+        }
+        
+        terms.extend(expansions);
+        terms
+    }
+    
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn set_oov_strategy(&mut self, strategy: OovStrategy) {
+        self.oov_strategy = strategy;
+    }
+    
+    // In streaming mode each training document is folded into the trained model as
+    // it arrives, so transform reflects it immediately. Only IDF and the vocabulary
+    // change; the LSA components stay fixed until the next full retrain, and cached
+    // vectors of earlier documents keep their old IDF weights until then.
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn set_streaming(&mut self, streaming: bool) {
+        self.streaming = streaming;
+    }
+    
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn is_streaming(&self) -> bool {
+        self.streaming
+    }
+    
+    // With a stable vocabulary, terms that survive a retrain keep their vocabulary
+    // index and new terms get higher indices, so external caches keyed by index
+    // stay valid. Indices of dropped terms are not reused.
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn set_stable_vocabulary(&mut self, enabled: bool) {
+        self.stable_vocabulary = enabled;
+    }
+    
+    // Queries shorter than this many characters (default 2, i.e. single-character
+    // queries) are treated as short: each kanji unigram is boosted. Applies to
+    // transform and its diagnostics, not to stored documents. 0 disables the
+    // special handling.
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn set_short_query_chars(&mut self, chars: usize) {
+        self.short_query_chars = chars;
+    }
+    
+    // With auto-retrain off, adding documents never starts a retrain; poll
+    // needs_retrain and call start_background_retrain when convenient
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn set_auto_retrain(&mut self, auto_retrain: bool) {
+        self.auto_retrain = auto_retrain;
+    }
+    
+    // L2 (default) or L1 scaling of transform output. Cached document and
+    // searchable vectors are recomputed with the new scaling.
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn set_normalization(&mut self, normalization: Normalization) {
+        if normalization == self.normalization {
+            return;
+        }
+        self.normalization = normalization;
+        self.hash_fallback = OnceLock::new();
+        
+        self.searchable_vectors = self.searchable_documents
+            .iter()
+            .map(|doc| self.transform(doc).unwrap_or_default())
+            .collect();
+        self.refresh_document_vectors();
+    }
+    
+    // Absolute trigger: start a retrain after every `every` changes, whichever of
+    // this and the update_threshold ratio is reached first (None disables it)
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn set_retrain_every(&mut self, every: Option<usize>) {
+        self.retrain_every = every.filter(|&every| every > 0);
+    }
+    
+    // Weight dictionary terms by boost in the TF-IDF matrix (1.0 disables)
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn set_dictionary_boost(&mut self, boost: f32) -> Result<(), JsValue> {
+        if !boost.is_finite() || boost <= 0.0 {
+            return Err(create_error("dictionary_boost must be a positive number"));
+        }
+        self.dictionary_boost = boost;
+        Ok(())
+    }
 
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
     pub fn start_background_retrain(&mut self, embedding_dim: usize) -> Result<(), JsValue> {
@@ -155,7 +526,11 @@ impl IncrementalEmbedder {
         self.is_retraining = true;
         self.retrain_progress = 0.0;
         self.retrain_step = RetrainStep::BuildingVocabulary;
-        self.pending_model = Some(TfIdfLsa::new(embedding_dim));
+        self.pending_model = Some(TfIdfLsa::with_config(TfIdfLsaConfig {
+            embedding_dim,
+            dictionary_boost: self.dictionary_boost,
+            ..self.model.config().clone()
+        }));
         
         Ok(())
     }
@@ -165,15 +540,35 @@ impl IncrementalEmbedder {
         if !self.is_retraining {
             return Ok(true);
         }
+        if self.retrain_paused {
+            return Err(create_error("Retraining is paused; call resume_retrain first"));
+        }
         
         match self.retrain_step {
             RetrainStep::Idle => Ok(true),
             
             RetrainStep::BuildingVocabulary => {
+                // Re-tokenize stored documents if the tokenizer settings changed.
+                // Without raw text the stored tokens are the only copy and are kept.
+                if self.tokenizer_changed {
+                    if self.store_raw_text {
+                        self.tokenized_documents = self.documents
+                            .iter()
+                            .map(|doc| self.tokenize(doc))
+                            .collect();
+                    }
+                    self.tokenizer_changed = false;
+                }
+                
                 // Build vocabulary (simulated as single step for simplicity)
-                let vocab = self.tokenizer.build_vocabulary(&self.documents);
+                let vocab = if self.stable_vocabulary {
+                    self.tokenizer.build_vocabulary_stable(&self.tokenized_documents, self.model.vocabulary())
+                } else {
+                    self.tokenizer.build_vocabulary_from_tokens(&self.tokenized_documents)
+                };
                 
                 if let Some(ref mut pending_model) = self.pending_model {
+                    pending_model.set_boosted_terms(self.tokenizer.dictionary_terms());
                     // Store vocabulary for next step
                     pending_model.fit(&self.tokenized_documents, vocab);
                 }
@@ -201,6 +596,7 @@ impl IncrementalEmbedder {
                 // Swap models
                 if let Some(new_model) = self.pending_model.take() {
                     self.model = new_model;
+                    self.hash_fallback = OnceLock::new();
                     
                     // Update searchable vectors with new model
                     self.searchable_vectors.clear();
@@ -209,6 +605,8 @@ impl IncrementalEmbedder {
                             self.searchable_vectors.push(vector);
                         }
                     }
+                    
+                    self.refresh_document_vectors();
                 }
                 
                 self.is_retraining = false;
@@ -220,8 +618,94 @@ impl IncrementalEmbedder {
         }
     }
 
+    // Remove the later document of every near-duplicate pair (see find_duplicates)
+    // from the training set and return how many were removed. Searchable documents
+    // are not affected; retrain afterwards so IDF reflects the cleaned corpus.
+    // Without raw text the removed documents stay in document_set, so adding them
+    // again is still treated as a duplicate.
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn dedup_near(&mut self, threshold: f32) -> usize {
+        let mut removed = vec![false; self.tokenized_documents.len()];
+        for (i, j) in self.find_duplicates(threshold) {
+            if !removed[i] {
+                removed[j] = true;
+            }
+        }
+        
+        let removed_count = removed.iter().filter(|&&r| r).count();
+        if removed_count == 0 {
+            return 0;
+        }
+        
+        for (text, _) in self.documents.iter().zip(&removed).filter(|(_, &r)| r) {
+            self.document_set.remove(text);
+        }
+        fn retain_unmarked<T>(items: &mut Vec<T>, removed: &[bool]) {
+            let mut flags = removed.iter();
+            items.retain(|_| !flags.next().copied().unwrap_or(false));
+        }
+        retain_unmarked(&mut self.documents, &removed);
+        retain_unmarked(&mut self.tokenized_documents, &removed);
+        retain_unmarked(&mut self.document_vectors, &removed);
+        
+        if let Some(ref index) = self.ann_index {
+            self.ann_index = Some(self.index_document_vectors(index.num_hyperplanes(), index.seed()));
+        }
+        
+        self.changes_since_update += removed_count;
+        removed_count
+    }
+
+    // Remove all documents and start over with an untrained model of the same
+    // configuration. Tokenizer and embedder settings are kept.
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn clear(&mut self) {
+        self.cancel_retrain().ok();
+        
+        self.documents.clear();
+        self.tokenized_documents.clear();
+        self.document_set.clear();
+        self.document_vectors.clear();
+        self.searchable_documents.clear();
+        self.searchable_vectors.clear();
+        self.searchable_set.clear();
+        self.changes_since_update = 0;
+        self.tokenizer_changed = false;
+        
+        self.model = TfIdfLsa::with_config(self.model.config().clone());
+        if let Some(ref index) = self.ann_index {
+            self.ann_index = Some(LshIndex::new(self.model.embedding_dim(), index.num_hyperplanes(), index.seed()));
+        }
+    }
+
+    // Freeze an in-progress retrain at its current step. The pending model is kept
+    // and is_retraining stays true; step_retrain fails until resume_retrain.
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn pause_retrain(&mut self) -> Result<(), JsValue> {
+        if !self.is_retraining {
+            return Err(create_error("No retraining in progress"));
+        }
+        self.retrain_paused = true;
+        Ok(())
+    }
+    
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn resume_retrain(&mut self) -> Result<(), JsValue> {
+        if !self.is_retraining {
+            return Err(create_error("No retraining in progress"));
+        }
+        self.retrain_paused = false;
+        Ok(())
+    }
+    
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn is_paused(&self) -> bool {
+        self.retrain_paused
+    }
+
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
     pub fn cancel_retrain(&mut self) -> Result<(), JsValue> {
+        self.retrain_paused = false;
         self.is_retraining = false;
         self.retrain_progress = 0.0;
         self.retrain_step = RetrainStep::Idle;
@@ -233,6 +717,18 @@ impl IncrementalEmbedder {
     pub fn is_retraining(&self) -> bool {
         self.is_retraining
     }
+    
+    // Whether a retrain is due but not running: the change ratio reached
+    // update_threshold, or the change count reached retrain_every. Adding documents
+    // starts the retrain automatically unless auto-retrain is off; this is for
+    // callers that trigger manually.
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn needs_retrain(&self) -> bool {
+        let change_ratio = self.changes_since_update as f32 / self.tokenized_documents.len().max(1) as f32;
+        let due = change_ratio >= self.update_threshold
+            || self.retrain_every.is_some_and(|every| self.changes_since_update >= every);
+        due && !self.is_retraining
+    }
 
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
     pub fn get_retrain_progress(&self) -> f32 {
@@ -246,9 +742,23 @@ impl IncrementalEmbedder {
     }
 
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    // The export carries the full document history (raw text, tokens and the
+    // dedup set) and retrain state, so an imported embedder can keep adding
+    // documents and retrain as if it had never been exported. Only the cached
+    // document vectors are rebuilt; an ANN index must be rebuilt by the caller.
     pub fn import_model(json_data: &str) -> Result<IncrementalEmbedder, JsValue> {
-        serde_json::from_str(json_data)
-            .map_err(|e| create_error(&format!("Failed to import model: {}", e)))
+        let version = read_format_version(json_data)
+            .map_err(|e| create_error(&format!("Failed to import model: {}", e)))?;
+        if version != FORMAT_VERSION {
+            return Err(create_error(&format!(
+                "Failed to import model: unsupported format version {} (expected {})",
+                version, FORMAT_VERSION
+            )));
+        }
+        let mut embedder: IncrementalEmbedder = serde_json::from_str(json_data)
+            .map_err(|e| create_error(&format!("Failed to import model: {}", e)))?;
+        embedder.refresh_document_vectors();
+        Ok(embedder)
     }
 
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
@@ -257,10 +767,62 @@ impl IncrementalEmbedder {
         let vec2 = self.transform(text2)?;
         Ok(cosine_similarity(&vec1, &vec2))
     }
+    
+    // Per-dimension products of the two L2-normalized embeddings; they sum to
+    // get_similarity, so the largest entries are the latent dimensions driving
+    // the match
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn similarity_breakdown(&self, text1: &str, text2: &str) -> Result<Vec<f32>, JsValue> {
+        let mut vec1 = self.transform(text1)?;
+        let mut vec2 = self.transform(text2)?;
+        l2_normalize(&mut vec1);
+        l2_normalize(&mut vec2);
+        Ok(vec1.iter().zip(&vec2).map(|(a, b)| a * b).collect())
+    }
+    
+    // get_similarity rounded to the given number of decimal places, for display
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn get_similarity_rounded(&self, text1: &str, text2: &str, decimals: u32) -> Result<f32, JsValue> {
+        let scale = 10f32.powi(decimals.min(9) as i32);
+        Ok((self.get_similarity(text1, text2)? * scale).round() / scale)
+    }
 
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
     pub fn get_document_count(&self) -> usize {
-        self.documents.len()
+        self.tokenized_documents.len()
+    }
+    
+    // Copy of all stored documents, in insertion order
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn get_documents(&self) -> Result<Vec<String>, JsValue> {
+        if !self.store_raw_text {
+            return Err(create_error("Raw document text is not stored (store_raw_text is off)"));
+        }
+        Ok(self.documents.clone())
+    }
+    
+    // Turning raw-text storage off drops the text of stored training documents
+    // (searchable documents are unaffected) to save memory. get_documents then
+    // fails, get_document returns None, and tokenizer changes only apply to
+    // documents added afterwards since stored ones can't be re-tokenized. It can't
+    // be turned back on once texts have been dropped.
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn set_store_raw_text(&mut self, store_raw_text: bool) -> Result<(), JsValue> {
+        if store_raw_text == self.store_raw_text {
+            return Ok(());
+        }
+        
+        if store_raw_text {
+            if !self.tokenized_documents.is_empty() {
+                return Err(create_error("Raw text of the stored documents was already dropped"));
+            }
+        } else {
+            self.document_set = self.documents.iter().map(|text| hash_document_text(text)).collect();
+            self.documents = Vec::new();
+        }
+        
+        self.store_raw_text = store_raw_text;
+        Ok(())
     }
     
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
@@ -273,10 +835,31 @@ impl IncrementalEmbedder {
         self.model.vocab_size()
     }
 
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn is_lsa_active(&self) -> bool {
+        self.model.is_lsa_active()
+    }
+
+    // Length of the vectors transform returns. While the model has fewer documents
+    // or terms than this, the LSA output is zero-padded.
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
     pub fn get_embedding_dim(&self) -> usize {
         self.model.embedding_dim()
     }
+    
+    // Check that this embedder produces vectors of the expected length, e.g. after
+    // import_model, before mixing its output with vectors stored elsewhere
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn assert_dimension(&self, expected: usize) -> Result<(), JsValue> {
+        if self.get_embedding_dim() != expected {
+            return Err(create_error(&format!(
+                "Model embedding dimension is {}, expected {}",
+                self.get_embedding_dim(),
+                expected
+            )));
+        }
+        Ok(())
+    }
 
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
     pub fn get_unique_document_count(&self) -> usize {
@@ -285,7 +868,7 @@ impl IncrementalEmbedder {
 
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
     pub fn contains_document(&self, text: &str) -> bool {
-        self.document_set.contains(text)
+        self.document_set.contains(&self.document_key(text))
     }
     
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
@@ -358,66 +941,1337 @@ impl IncrementalEmbedder {
             .map_err(|e| create_error(&format!("Failed to serialize results: {}", e)))
     }
     
+    // nearest_documents as a JSON array of {index, score} objects for JS callers
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn nearest_documents_json(&self, query: &str, k: usize) -> Result<String, JsValue> {
+        let results: Vec<serde_json::Value> = self
+            .nearest_documents(query, k)?
+            .into_iter()
+            .map(|(index, score)| serde_json::json!({ "index": index, "score": score }))
+            .collect();
+        
+        serde_json::to_string(&results)
+            .map_err(|e| create_error(&format!("Failed to serialize results: {}", e)))
+    }
+    
+    // Cluster stored documents with spherical k-means (cosine distance) over the
+    // cached embeddings. Centroids are seeded with k-means++ from a fixed seed, so
+    // the same collection always yields the same assignment (one label per document).
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn cluster(&self, k: usize, max_iters: usize) -> Vec<usize> {
+        let vectors = &self.document_vectors;
+        let n = vectors.len();
+        if n == 0 || k == 0 {
+            return Vec::new();
+        }
+        let k = k.min(n);
+        
+        // k-means++ seeding: pick each new centroid with probability proportional
+        // to its squared cosine distance from the nearest existing centroid
+        let mut rng = SeededRng::new(42);
+        let mut centroids = vec![vectors[(rng.next_u64() % n as u64) as usize].clone()];
+        while centroids.len() < k {
+            let distances: Vec<f32> = vectors
+                .iter()
+                .map(|vec| {
+                    let max_sim = centroids
+                        .iter()
+                        .map(|centroid| cosine_similarity(vec, centroid))
+                        .fold(f32::MIN, f32::max);
+                    (1.0 - max_sim).max(0.0).powi(2)
+                })
+                .collect();
+            let total: f32 = distances.iter().sum();
+            
+            let next = if total > 0.0 {
+                let mut target = rng.next_f32() * total;
+                let mut chosen = n - 1;
+                for (idx, &distance) in distances.iter().enumerate() {
+                    if target < distance {
+                        chosen = idx;
+                        break;
+                    }
+                    target -= distance;
+                }
+                chosen
+            } else {
+                // All documents coincide with a centroid; fall back to sequential picks
+                centroids.len()
+            };
+            centroids.push(vectors[next].clone());
+        }
+        
+        let mut assignments = vec![0usize; n];
+        for iteration in 0..max_iters.max(1) {
+            // Assign each document to its most similar centroid
+            let mut changed = false;
+            for (idx, vec) in vectors.iter().enumerate() {
+                let mut best = 0;
+                let mut best_sim = f32::MIN;
+                for (cluster, centroid) in centroids.iter().enumerate() {
+                    let sim = cosine_similarity(vec, centroid);
+                    if sim > best_sim {
+                        best_sim = sim;
+                        best = cluster;
+                    }
+                }
+                if assignments[idx] != best {
+                    assignments[idx] = best;
+                    changed = true;
+                }
+            }
+            
+            if !changed && iteration > 0 {
+                break;
+            }
+            
+            // Recompute centroids as normalized means; empty clusters keep their centroid
+            for (cluster, centroid) in centroids.iter_mut().enumerate() {
+                let mut sum = vec![0.0f32; centroid.len()];
+                let mut count = 0;
+                for (vec, _) in vectors.iter().zip(&assignments).filter(|(_, &a)| a == cluster) {
+                    for (s, v) in sum.iter_mut().zip(vec) {
+                        *s += v;
+                    }
+                    count += 1;
+                }
+                if count > 0 {
+                    l2_normalize(&mut sum);
+                    *centroid = sum;
+                }
+            }
+        }
+        
+        assignments
+    }
+    
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn get_searchable_count(&self) -> usize {
+        self.searchable_documents.len()
+    }
+    
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
-    pub fn get_searchable_count(&self) -> usize {
-        self.searchable_documents.len()
-    }
-    
-    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
     pub fn set_dictionary(&mut self, dictionary_json: &str) -> Result<(), JsValue> {
         let entries: Vec<DictionaryEntry> = serde_json::from_str(dictionary_json)
             .map_err(|e| create_error(&format!("Failed to parse dictionary: {}", e)))?;
         
-        self.tokenizer.set_user_dictionary(entries);
-        Ok(())
+        self.set_user_dictionary(entries);
+        Ok(())
+    }
+    
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn clear_dictionary(&mut self) {
+        self.tokenizer.clear_user_dictionary();
+        self.mark_tokenizer_changed();
+    }
+    
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn set_stop_words_enabled(&mut self, enabled: bool) {
+        self.tokenizer.set_stop_words_enabled(enabled);
+        self.mark_tokenizer_changed();
+    }
+    
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn add_stop_word(&mut self, word: &str) {
+        self.tokenizer.add_stop_word(word);
+        self.mark_tokenizer_changed();
+    }
+    
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn remove_stop_word(&mut self, word: &str) {
+        self.tokenizer.remove_stop_word(word);
+        self.mark_tokenizer_changed();
+    }
+    
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn set_builtin_preprocessor(&mut self, preprocessor: Preprocessor) {
+        self.preprocessor = preprocessor;
+        self.mark_tokenizer_changed();
+    }
+}
+
+// Non-WASM methods for internal use
+impl IncrementalEmbedder {
+    // Create an embedder around a fully configured tokenizer
+    pub fn with_tokenizer(update_threshold: f32, tokenizer: JapaneseTokenizer) -> Self {
+        Self {
+            tokenizer,
+            model: TfIdfLsa::new(64),
+            documents: Vec::new(),
+            tokenized_documents: Vec::new(),
+            document_set: HashSet::new(),
+            update_threshold,
+            changes_since_update: 0,
+            is_retraining: false,
+            retrain_progress: 0.0,
+            pending_model: None,
+            retrain_step: RetrainStep::Idle,
+            searchable_documents: Vec::new(),
+            searchable_vectors: Vec::new(),
+            searchable_set: HashSet::new(),
+            document_vectors: Vec::new(),
+            ann_index: None,
+            min_doc_chars: 0,
+            max_doc_chars: 0,
+            tokenizer_changed: false,
+            oov_strategy: OovStrategy::ZeroVector,
+            dictionary_boost: default_dictionary_boost(),
+            streaming: false,
+            retrain_every: None,
+            auto_retrain: default_auto_retrain(),
+            store_raw_text: default_store_raw_text(),
+            normalization: Normalization::L2,
+            retrain_paused: false,
+            stable_vocabulary: false,
+            short_query_chars: default_short_query_chars(),
+            format_version: FORMAT_VERSION,
+            preprocessor: Preprocessor::None,
+            custom_preprocessor: None,
+            hash_fallback: OnceLock::new(),
+        }
+    }
+    
+    // Check a document against min_doc_chars/max_doc_chars (0 means unlimited)
+    fn is_within_length_limits(&self, text: &str) -> bool {
+        let char_count = text.trim().chars().count();
+        
+        if self.min_doc_chars > 0 && char_count < self.min_doc_chars {
+            return false;
+        }
+        if self.max_doc_chars > 0 && char_count > self.max_doc_chars {
+            return false;
+        }
+        
+        true
+    }
+    
+    // Entry of a training document in document_set
+    fn document_key(&self, text: &str) -> String {
+        if self.store_raw_text {
+            text.to_string()
+        } else {
+            hash_document_text(text)
+        }
+    }
+    
+    // Tokenizer settings changed: stored tokens are stale and the model needs a retrain
+    fn mark_tokenizer_changed(&mut self) {
+        self.tokenizer_changed = true;
+        self.changes_since_update += 1;
+    }
+    
+    // Custom cleanup applied to every text (documents and queries) before
+    // tokenization, after the built-in preprocessor. Like other tokenizer changes,
+    // stored documents are re-tokenized at the next retrain.
+    pub fn set_preprocessor(&mut self, preprocessor: Box<dyn Fn(&str) -> String + Send + Sync>) {
+        self.custom_preprocessor = Some(Arc::from(preprocessor));
+        self.mark_tokenizer_changed();
+    }
+    
+    pub fn clear_preprocessor(&mut self) {
+        self.custom_preprocessor = None;
+        self.mark_tokenizer_changed();
+    }
+    
+    // Tokenize text after applying the preprocessors
+    fn tokenize(&self, text: &str) -> Vec<String> {
+        let text = self.preprocessor.apply(text);
+        match self.custom_preprocessor {
+            Some(ref preprocess) => self.tokenizer.tokenize(&preprocess(&text)),
+            None => self.tokenizer.tokenize(&text),
+        }
+    }
+    
+    // Add a training document built from weighted fields (e.g. title and body).
+    // Each field's tokens are repeated round(weight) times, so a field with weight
+    // 2.0 counts twice towards TF; fields with weight below 0.5 are dropped. The
+    // stored text is the fields joined by newlines. Re-tokenizing after a tokenizer
+    // change works from that text, so the weighting is lost in that case.
+    pub fn add_document_fields(&mut self, fields: Vec<(String, f32)>, embedding_dim: usize) -> Result<(), JsValue> {
+        let text = fields
+            .iter()
+            .map(|(field, _)| field.as_str())
+            .collect::<Vec<_>>()
+            .join("\n");
+        if !self.is_within_length_limits(&text) || self.document_set.contains(&self.document_key(&text)) {
+            return Ok(());
+        }
+        
+        let mut tokens = Vec::new();
+        for (field, weight) in &fields {
+            let field_tokens = self.tokenize(field);
+            for _ in 0..weight.round().max(0.0) as usize {
+                tokens.extend(field_tokens.iter().cloned());
+            }
+        }
+        
+        self.store_training_document(text, tokens);
+        self.trigger_auto_retrain(embedding_dim)
+    }
+    
+    // Store a new (non-duplicate) training document
+    fn store_training_document(&mut self, text: String, tokens: Vec<String>) {
+        self.document_set.insert(self.document_key(&text));
+        if self.store_raw_text {
+            self.documents.push(text);
+        }
+        
+        // Streaming mode: update document frequencies and IDF right away
+        if self.streaming && self.model.documents_count() > 0 {
+            self.model.fold_in(&tokens);
+        }
+        self.push_document_vector(&tokens);
+        self.tokenized_documents.push(tokens);
+        
+        self.changes_since_update += 1;
+    }
+    
+    // Start a retrain once enough of the collection has changed
+    fn trigger_auto_retrain(&mut self, embedding_dim: usize) -> Result<(), JsValue> {
+        if self.auto_retrain && self.needs_retrain() {
+            self.start_background_retrain(embedding_dim)?;
+        }
+        Ok(())
+    }
+    
+    // Add text to the searchable documents with its precomputed vector, unless
+    // already present
+    fn add_searchable_document(&mut self, text: String) -> Result<(), JsValue> {
+        if self.searchable_set.contains(&text) {
+            return Ok(());
+        }
+        
+        let vector = self.transform(&text)?;
+        self.searchable_set.insert(text.clone());
+        self.searchable_documents.push(text);
+        self.searchable_vectors.push(vector);
+        Ok(())
+    }
+    
+    // Embedding of a document tokenized elsewhere (e.g. with `tokenizer().tokenize`),
+    // skipping the tokenizer. The OOV hash fallback needs the raw text, so an
+    // out-of-vocabulary token list always maps to the zero vector.
+    pub fn transform_tokens(&self, tokens: &[String]) -> Result<Vec<f32>, JsValue> {
+        Ok(self.embed_tokens(tokens))
+    }
+    
+    // Tokens transform embeds: the text's tokens, plus kanji signal for short queries
+    fn query_tokens(&self, text: &str) -> Vec<String> {
+        let mut tokens = self.tokenize(text);
+        if text.trim().chars().count() < self.short_query_chars {
+            self.add_short_query_tokens(text, &mut tokens);
+        }
+        tokens
+    }
+    
+    // A lone kanji rarely has much weight of its own, so short queries repeat each
+    // kanji unigram to boost its TF
+    fn add_short_query_tokens(&self, text: &str, tokens: &mut Vec<String>) {
+        for kanji in self.tokenizer.kanji_unigrams(text) {
+            tokens.extend(std::iter::repeat_n(kanji, SHORT_QUERY_KANJI_BOOST));
+        }
+    }
+    
+    // Embed already tokenized text with the current model
+    fn embed_tokens(&self, tokens: &[String]) -> Vec<f32> {
+        let mut embedding = self.model.transform(tokens);
+        self.pad_to_embedding_dim(&mut embedding);
+        normalize(&mut embedding, self.normalization);
+        embedding
+    }
+    
+    // LSA fitted on fewer documents or terms than embedding_dim yields shorter
+    // vectors; zero padding keeps every embedding the same length without
+    // changing cosine similarities
+    fn pad_to_embedding_dim(&self, embedding: &mut Vec<f32>) {
+        if embedding.len() < self.get_embedding_dim() {
+            embedding.resize(self.get_embedding_dim(), 0.0);
+        }
+    }
+    
+    // StableHashEmbedder embedding of text, for OovStrategy::HashFallback
+    fn hash_fallback_embedding(&self, text: &str) -> Vec<f32> {
+        let fallback = self.hash_fallback.get_or_init(|| {
+            let mut fallback = StableHashEmbedder::new(self.get_embedding_dim(), 2);
+            fallback.set_normalization(self.normalization);
+            fallback
+        });
+        fallback.transform(text)
+    }
+    
+    // Cache the embedding of a newly stored document
+    fn push_document_vector(&mut self, tokens: &[String]) {
+        let vector = self.embed_tokens(tokens);
+        if let Some(ref mut index) = self.ann_index {
+            index.insert(self.document_vectors.len(), &vector);
+        }
+        self.document_vectors.push(vector);
+    }
+    
+    // Recompute the cached embedding of every stored document
+    fn refresh_document_vectors(&mut self) {
+        self.document_vectors = self.tokenized_documents
+            .iter()
+            .map(|tokens| self.embed_tokens(tokens))
+            .collect();
+        
+        if let Some(ref index) = self.ann_index {
+            self.ann_index = Some(self.index_document_vectors(index.num_hyperplanes(), index.seed()));
+        }
+    }
+    
+    // Rank the given stored documents by cosine similarity to a query vector
+    fn rank_documents(&self, query_vec: &[f32], candidates: impl Iterator<Item = usize>, k: usize) -> Vec<(usize, f32)> {
+        let mut similarities: Vec<(usize, f32)> = candidates
+            .map(|idx| (idx, cosine_similarity(query_vec, &self.document_vectors[idx])))
+            .collect();
+        similarities.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        similarities.truncate(k);
+        similarities
+    }
+    
+    // Exact top-k stored documents (index, score) for a query
+    pub fn nearest_documents(&self, query: &str, k: usize) -> Result<Vec<(usize, f32)>, JsValue> {
+        let query_vec = self.transform(query)?;
+        Ok(self.rank_documents(&query_vec, 0..self.document_vectors.len(), k))
+    }
+    
+    // nearest_documents returning the stored texts instead of their indices.
+    // Needs store_raw_text.
+    pub fn search(&self, query: &str, k: usize) -> Result<Vec<(String, f32)>, JsValue> {
+        if !self.store_raw_text {
+            return Err(create_error("search needs the raw document text (store_raw_text is off)"));
+        }
+        Ok(self
+            .nearest_documents(query, k)?
+            .into_iter()
+            .map(|(index, score)| (self.documents[index].clone(), score))
+            .collect())
+    }
+    
+    // "More like this": top-k stored documents most similar to stored document
+    // `index` (itself excluded), using the cached embeddings. One row of
+    // similarity_matrix without computing the rest.
+    pub fn similar_to_document(&self, index: usize, k: usize) -> Result<Vec<(usize, f32)>, JsValue> {
+        let Some(query_vec) = self.document_vectors.get(index) else {
+            return Err(create_error(&format!(
+                "Document index {} out of range ({} documents)",
+                index,
+                self.document_vectors.len()
+            )));
+        };
+        let others = (0..self.document_vectors.len()).filter(|&i| i != index);
+        Ok(self.rank_documents(query_vec, others, k))
+    }
+    
+    // Exact top-k stored documents for a precomputed query embedding, which must
+    // have the length transform produces (get_embedding_dim)
+    pub fn nearest_to_vector(&self, query_vec: &[f32], k: usize) -> Result<Vec<(usize, f32)>, JsValue> {
+        let expected_dim = self.get_embedding_dim();
+        if query_vec.len() != expected_dim {
+            return Err(create_error(&format!(
+                "Query vector has dimension {}, expected {}",
+                query_vec.len(),
+                expected_dim
+            )));
+        }
+        Ok(self.rank_documents(query_vec, 0..self.document_vectors.len(), k))
+    }
+    
+    // Build (or rebuild) the LSH index used by nearest_documents_approx. More
+    // hyperplanes make queries faster but lower recall. The index is kept up to
+    // date as documents are added and rebuilt after each retrain.
+    pub fn build_ann_index(&mut self, num_hyperplanes: usize) {
+        self.ann_index = Some(self.index_document_vectors(num_hyperplanes, 42));
+    }
+    
+    // LSH index over document_vectors. The dimension comes from the model rather
+    // than the vectors, so an index built on an empty collection still hashes
+    // documents added later.
+    fn index_document_vectors(&self, num_hyperplanes: usize, seed: u64) -> LshIndex {
+        let mut index = LshIndex::new(self.get_embedding_dim(), num_hyperplanes, seed);
+        for (id, vector) in self.document_vectors.iter().enumerate() {
+            index.insert(id, vector);
+        }
+        index
+    }
+    
+    // Approximate top-k stored documents, scanning only LSH candidate buckets
+    pub fn nearest_documents_approx(&self, query: &str, k: usize) -> Result<Vec<(usize, f32)>, JsValue> {
+        let index = self.ann_index
+            .as_ref()
+            .ok_or_else(|| create_error("ANN index not built; call build_ann_index first"))?;
+        let query_vec = self.transform(query)?;
+        Ok(self.rank_documents(&query_vec, index.candidates(&query_vec).into_iter(), k))
+    }
+    
+    // Pairwise cosine similarity of all stored documents, indexed like `documents`.
+    // This is O(N²) in the number of documents, so avoid calling it on hot paths.
+    pub fn similarity_matrix(&self) -> Vec<Vec<f32>> {
+        let n = self.document_vectors.len();
+        let mut matrix = vec![vec![0.0; n]; n];
+        
+        for (i, vec_i) in self.document_vectors.iter().enumerate() {
+            for (j, vec_j) in self.document_vectors.iter().enumerate().skip(i) {
+                let similarity = cosine_similarity(vec_i, vec_j);
+                matrix[i][j] = similarity;
+                matrix[j][i] = similarity;
+            }
+        }
+        
+        matrix
+    }
+    
+    // Mean cosine similarity between each stored document's embedding here and in
+    // previous (typically a clone taken before retraining), matched by index and,
+    // when both keep raw text, by text. Near 1.0 means the retrain barely moved
+    // the documents; LSA components can also flip sign or reorder when the corpus
+    // changes a lot, which shows up as low values. 0.0 when nothing matches.
+    pub fn retrain_drift(&self, previous: &IncrementalEmbedder) -> f32 {
+        let compare_text = self.store_raw_text && previous.store_raw_text;
+        let similarities: Vec<f32> = self.document_vectors
+            .iter()
+            .zip(&previous.document_vectors)
+            .enumerate()
+            .filter(|(i, _)| !compare_text || self.documents.get(*i) == previous.documents.get(*i))
+            .map(|(_, (current, before))| cosine_similarity(current, before))
+            .collect();
+        
+        if similarities.is_empty() {
+            return 0.0;
+        }
+        similarities.iter().sum::<f32>() / similarities.len() as f32
+    }
+    
+    // Mean of the cached embeddings of the given stored documents, L2-normalized
+    pub fn centroid(&self, indices: &[usize]) -> Result<Vec<f32>, JsValue> {
+        if indices.is_empty() {
+            return Err(create_error("Cannot compute the centroid of no documents"));
+        }
+        
+        let mut centroid: Vec<f32> = Vec::new();
+        for &idx in indices {
+            let vector = self.document_vectors
+                .get(idx)
+                .ok_or_else(|| create_error(&format!("Document index {} out of range", idx)))?;
+            if centroid.is_empty() {
+                centroid = vec![0.0; vector.len()];
+            }
+            for (c, v) in centroid.iter_mut().zip(vector) {
+                *c += v;
+            }
+        }
+        
+        for c in centroid.iter_mut() {
+            *c /= indices.len() as f32;
+        }
+        l2_normalize(&mut centroid);
+        Ok(centroid)
+    }
+    
+    // Pairs (i, j) with i < j of stored documents whose cached embeddings have a
+    // cosine similarity above threshold. O(N²) like similarity_matrix.
+    pub fn find_duplicates(&self, threshold: f32) -> Vec<(usize, usize)> {
+        let mut pairs = Vec::new();
+        
+        for (i, vec_i) in self.document_vectors.iter().enumerate() {
+            for (j, vec_j) in self.document_vectors.iter().enumerate().skip(i + 1) {
+                if cosine_similarity(vec_i, vec_j) > threshold {
+                    pairs.push((i, j));
+                }
+            }
+        }
+        
+        pairs
+    }
+    
+    pub fn set_user_dictionary(&mut self, entries: Vec<DictionaryEntry>) {
+        self.tokenizer.set_user_dictionary(entries);
+        self.mark_tokenizer_changed();
+    }
+    
+    // Sparse TF-IDF rows of all stored documents under the current model. Together
+    // with feature_names these reconstruct a CSR matrix for external ML tools.
+    pub fn export_tfidf_matrix(&self) -> Vec<Vec<(usize, f32)>> {
+        self.tokenized_documents
+            .iter()
+            .map(|tokens| self.model.tfidf_sparse(tokens))
+            .collect()
+    }
+    
+    pub fn feature_names(&self) -> Vec<String> {
+        self.model.feature_names()
+    }
+    
+    // The top_n tokens of a text by TF-IDF weight (strongest first), for tagging.
+    // Tokens outside the model's vocabulary are skipped. Weighted like transform,
+    // including the short-query kanji boost.
+    pub fn keywords(&self, text: &str, top_n: usize) -> Vec<(String, f32)> {
+        let tokens = self.query_tokens(text);
+        let weights = self.model.tfidf_sparse(&tokens);
+        let vocabulary = self.model.vocabulary();
+        
+        let mut keywords: Vec<(String, f32)> = tokens
+            .iter()
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .filter_map(|token| {
+                let idx = *vocabulary.get(token)?;
+                let pos = weights.binary_search_by_key(&idx, |&(i, _)| i).ok()?;
+                Some((token.clone(), weights[pos].1))
+            })
+            .collect();
+        keywords.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        keywords.truncate(top_n);
+        keywords
+    }
+    
+    pub fn get_document(&self, index: usize) -> Option<&str> {
+        self.documents.get(index).map(|doc| doc.as_str())
+    }
+    
+    pub fn documents(&self) -> &[String] {
+        &self.documents
+    }
+    
+    pub fn tokenizer(&self) -> &JapaneseTokenizer {
+        &self.tokenizer
+    }
+    
+    // Change tokenizer settings directly; counted as a tokenizer change, which
+    // takes effect on the next retrain. Read-only access goes through tokenizer().
+    pub fn update_tokenizer<R>(&mut self, update: impl FnOnce(&mut JapaneseTokenizer) -> R) -> R {
+        self.mark_tokenizer_changed();
+        update(&mut self.tokenizer)
+    }
+
+    // Native convenience: run a full retrain to completion, reporting progress
+    // after each step. An already running retrain is driven to completion instead.
+    pub fn retrain_blocking(&mut self, embedding_dim: usize, mut progress: impl FnMut(f32)) -> Result<(), JsValue> {
+        if !self.is_retraining {
+            self.start_background_retrain(embedding_dim)?;
+        }
+        
+        loop {
+            let done = self.step_retrain()?;
+            progress(self.retrain_progress);
+            if done {
+                return Ok(());
+            }
+        }
+    }
+    
+    pub fn transform_batch(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, JsValue> {
+        texts.iter()
+            .map(|text| self.transform(text))
+            .collect()
+    }
+
+    pub fn get_similarity_batch(&self, query: &str, candidates: Vec<String>) -> Result<Vec<f32>, JsValue> {
+        let query_vec = self.transform(query)?;
+        
+        candidates.iter()
+            .map(|candidate| {
+                let candidate_vec = self.transform(candidate)?;
+                Ok(cosine_similarity(&query_vec, &candidate_vec))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_incremental_embedder_basic() {
+        let mut embedder = IncrementalEmbedder::new(0.5); // Higher threshold to avoid auto-retrain
+        
+        // Add documents
+        embedder.add_document("今日は天気がいいですね".to_string(), 64).unwrap();
+        embedder.add_document("明日は雨が降りそうです".to_string(), 64).unwrap();
+        embedder.add_document("今日は映画を見ました".to_string(), 64).unwrap();
+        
+        // Transform a document
+        let embedding = embedder.transform("今日は晴れです").unwrap();
+        assert_eq!(embedding.len(), 64);
+        
+        // Check similarity
+        let sim = embedder.get_similarity("今日は天気がいい", "明日は天気がいい").unwrap();
+        assert!((-1.0..=1.0).contains(&sim));
+        
+        let rounded = embedder.get_similarity_rounded("今日は天気がいい", "明日は天気がいい", 2).unwrap();
+        assert_eq!(rounded, (sim * 100.0).round() / 100.0);
+    }
+
+    #[test]
+    fn test_empty_input_yields_zero_vector() {
+        let mut embedder = IncrementalEmbedder::new(0.5);
+        embedder.add_document("今日は天気がいいですね".to_string(), 64).unwrap();
+        embedder.add_document("明日は雨が降りそうです".to_string(), 64).unwrap();
+        
+        for text in ["", "   "] {
+            let embedding = embedder.transform(text).unwrap();
+            assert_eq!(embedding.len(), embedder.get_embedding_dim());
+            assert!(crate::utils::is_zero_vector(&embedding));
+            
+            let sim = embedder.get_similarity(text, "今日は天気がいいですね").unwrap();
+            assert_eq!(sim, 0.0);
+        }
+    }
+
+    #[test]
+    fn test_oov_hash_fallback() {
+        let mut embedder = IncrementalEmbedder::new(2.0);
+        embedder.add_document("今日は天気がいいですね".to_string(), 32).unwrap();
+        embedder.add_document("明日は雨が降りそうです".to_string(), 32).unwrap();
+        embedder.add_document("今日は映画を見ました".to_string(), 32).unwrap();
+        embedder.start_background_retrain(32).unwrap();
+        while !embedder.step_retrain().unwrap() {}
+        
+        let query = "魑魅魍魎";
+        assert!(is_zero_vector(&embedder.transform(query).unwrap()));
+        
+        embedder.set_oov_strategy(OovStrategy::HashFallback);
+        let embedding = embedder.transform(query).unwrap();
+        assert!(!is_zero_vector(&embedding));
+        assert_eq!(embedding.len(), embedder.transform("今日は天気").unwrap().len());
+        assert_eq!(embedder.transform(query).unwrap(), embedding);
+        assert_eq!(embedder.hash_fallback.get().map(|fallback| fallback.get_dimension()), Some(32));
+        
+        // A retrain at another dimension rebuilds the fallback
+        embedder.retrain_blocking(16, |_| {}).unwrap();
+        assert!(embedder.hash_fallback.get().is_none());
+        assert_eq!(embedder.transform(query).unwrap().len(), 16);
+    }
+
+    #[test]
+    fn test_streaming_updates_idf_immediately() {
+        let mut embedder = IncrementalEmbedder::new(2.0);
+        embedder.add_document("今日は天気がいいですね".to_string(), 32).unwrap();
+        embedder.add_document("明日は雨が降りそうです".to_string(), 32).unwrap();
+        embedder.retrain_blocking(32, |_| {}).unwrap();
+        assert!(embedder.model.idf_for_token("映画").is_none());
+        
+        embedder.set_streaming(true);
+        embedder.add_document("今日は映画を見ました".to_string(), 32).unwrap();
+        assert!(!embedder.is_retraining());
+        assert!(embedder.model.idf_for_token("映画").unwrap() > 0.0);
+        assert_eq!(embedder.model.documents_count(), 3);
+    }
+
+    #[test]
+    fn test_transform_tokens_matches_transform() {
+        let mut embedder = IncrementalEmbedder::new(2.0);
+        embedder.add_document("今日は天気がいいですね".to_string(), 16).unwrap();
+        embedder.add_document("明日は雨が降りそうです".to_string(), 16).unwrap();
+        embedder.retrain_blocking(16, |_| {}).unwrap();
+        
+        let text = "今日は雨です";
+        let tokens = embedder.tokenizer().tokenize(text);
+        assert_eq!(embedder.transform_tokens(&tokens).unwrap(), embedder.transform(text).unwrap());
+    }
+
+    #[test]
+    fn test_find_duplicates() {
+        let mut embedder = IncrementalEmbedder::new(2.0);
+        for text in [
+            "東京の今日の天気は晴れで、気温は二十度まで上がる見込みです",
+            "東京の今日の天気は晴れ、気温は二十度まで上がる見込みです",
+            "明日は雨が降りそうです",
+            "週末に映画を見ました",
+        ] {
+            embedder.add_document(text.to_string(), 16).unwrap();
+        }
+        embedder.retrain_blocking(16, |_| {}).unwrap();
+        
+        let duplicates = embedder.find_duplicates(0.9);
+        assert_eq!(duplicates, vec![(0, 1)]);
+        
+        assert_eq!(embedder.dedup_near(0.9), 1);
+        assert_eq!(embedder.get_document_count(), 3);
+        assert!(!embedder.contains_document("東京の今日の天気は晴れ、気温は二十度まで上がる見込みです"));
+        assert!(embedder.find_duplicates(0.9).is_empty());
+    }
+
+    #[test]
+    fn test_add_document_fields_weights_title() {
+        let mut embedder = IncrementalEmbedder::new(2.0);
+        embedder.add_document_for_training("明日は雨が降りそうです".to_string(), 16).unwrap();
+        embedder.add_document_for_training("今日は映画を見ました".to_string(), 16).unwrap();
+        embedder
+            .add_document_fields(vec![("天気予報".to_string(), 3.0), ("野球観戦".to_string(), 1.0)], 16)
+            .unwrap();
+        assert_eq!(embedder.get_document(2), Some("天気予報\n野球観戦"));
+        embedder.retrain_blocking(16, |_| {}).unwrap();
+        
+        // Both terms occur once in the raw text and only in this document
+        let names = embedder.feature_names();
+        let weight = |term: &str| {
+            let idx = names.iter().position(|name| name == term).unwrap();
+            embedder.export_tfidf_matrix()[2]
+                .iter()
+                .find(|(i, _)| *i == idx)
+                .map(|(_, w)| *w)
+                .unwrap()
+        };
+        assert!(weight("天気") > weight("野球"));
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut embedder = IncrementalEmbedder::new(2.0);
+        embedder.add_document("今日は天気がいいですね".to_string(), 16).unwrap();
+        embedder.add_document("明日は雨が降りそうです".to_string(), 16).unwrap();
+        embedder.retrain_blocking(16, |_| {}).unwrap();
+        embedder.add_document("今日は映画を見ました".to_string(), 16).unwrap();
+        embedder.start_background_retrain(16).unwrap();
+        assert!(embedder.get_vocab_size() > 0);
+        
+        embedder.clear();
+        assert_eq!(embedder.get_document_count(), 0);
+        assert_eq!(embedder.get_searchable_count(), 0);
+        assert_eq!(embedder.get_vocab_size(), 0);
+        assert_eq!(embedder.get_embedding_dim(), 16);
+        assert!(!embedder.is_retraining());
+        
+        // Reusable for a new corpus
+        embedder.add_document("今日は天気がいいですね".to_string(), 16).unwrap();
+        assert_eq!(embedder.get_document_count(), 1);
+    }
+
+    #[test]
+    fn test_retrain_every() {
+        let mut embedder = IncrementalEmbedder::new(0.5);
+        for i in 0..20 {
+            embedder.add_document_for_training(format!("背景知識{}", i), 16).unwrap();
+        }
+        embedder.retrain_blocking(16, |_| {}).unwrap();
+        embedder.set_retrain_every(Some(3));
+        
+        // The 0.5 ratio would need 20 more documents
+        embedder.add_document_for_training("今日は天気がいいですね".to_string(), 16).unwrap();
+        embedder.add_document_for_training("明日は雨が降りそうです".to_string(), 16).unwrap();
+        assert!(!embedder.is_retraining());
+        embedder.add_document_for_training("今日は映画を見ました".to_string(), 16).unwrap();
+        assert!(embedder.is_retraining());
+    }
+
+    #[test]
+    fn test_keywords() {
+        let mut embedder = IncrementalEmbedder::new(2.0);
+        for text in ["天気 映画 富士山", "天気 映画", "天気 映画 雨", "映画 天気 晴れ"] {
+            embedder.add_document_for_training(text.to_string(), 4).unwrap();
+        }
+        embedder.retrain_blocking(4, |_| {}).unwrap();
+        
+        let keywords = embedder.keywords("天気 映画 富士山", 3);
+        assert_eq!(keywords.len(), 3);
+        // The 富士山 n-grams are the rarest terms and tie, so ties are broken lexically
+        let terms: Vec<&str> = keywords.iter().map(|(term, _)| term.as_str()).collect();
+        assert_eq!(terms, vec!["士", "士山", "富"]);
+        assert!(keywords.windows(2).all(|pair| pair[0].1 >= pair[1].1));
+        
+        // Out-of-vocabulary text has no keywords
+        assert!(embedder.keywords("魑魅魍魎", 3).is_empty());
+    }
+
+    #[test]
+    fn test_needs_retrain() {
+        let mut embedder = IncrementalEmbedder::new(0.5);
+        for i in 0..4 {
+            embedder.add_document_for_training(format!("背景知識{}", i), 16).unwrap();
+        }
+        embedder.retrain_blocking(16, |_| {}).unwrap();
+        assert!(!embedder.needs_retrain());
+        
+        // merge adds documents without starting a retrain
+        let mut other = IncrementalEmbedder::new(2.0);
+        for i in 0..3 {
+            other.add_document_for_training(format!("追加文書{}", i), 16).unwrap();
+        }
+        embedder.merge(&other).unwrap();
+        assert!(!embedder.needs_retrain());
+        
+        other.add_document_for_training("追加文書3".to_string(), 16).unwrap();
+        embedder.merge(&other).unwrap();
+        assert!(embedder.needs_retrain());
+        
+        embedder.start_background_retrain(16).unwrap();
+        assert!(!embedder.needs_retrain());
     }
-    
-    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
-    pub fn clear_dictionary(&mut self) {
-        self.tokenizer.clear_user_dictionary();
+
+    #[test]
+    fn test_auto_retrain_disabled() {
+        let mut embedder = IncrementalEmbedder::new(0.1);
+        embedder.set_auto_retrain(false);
+        for i in 0..20 {
+            embedder.add_document(format!("文書番号{}", i), 16).unwrap();
+            assert!(!embedder.is_retraining());
+        }
+        assert!(embedder.needs_retrain());
+        
+        embedder.start_background_retrain(16).unwrap();
+        while !embedder.step_retrain().unwrap() {}
+        assert!(!embedder.needs_retrain());
     }
-}
 
-// Non-WASM methods for internal use
-impl IncrementalEmbedder {
-    pub fn transform_batch(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, JsValue> {
-        texts.iter()
-            .map(|text| self.transform(text))
-            .collect()
+    #[test]
+    fn test_nearest_to_vector() {
+        let mut embedder = IncrementalEmbedder::new(2.0);
+        for text in ["今日は天気がいいですね", "明日は雨が降りそうです", "今日は映画を見ました"] {
+            embedder.add_document(text.to_string(), 16).unwrap();
+        }
+        embedder.retrain_blocking(16, |_| {}).unwrap();
+        
+        let query_vec = embedder.transform("明日は雨が降りそうです").unwrap();
+        let results = embedder.nearest_to_vector(&query_vec, 3).unwrap();
+        assert_eq!(results[0].0, 1);
+        assert!((results[0].1 - 1.0).abs() < 1e-5);
+        
+        assert!(embedder.nearest_to_vector(&[1.0, 0.0], 3).is_err());
     }
 
-    pub fn get_similarity_batch(&self, query: &str, candidates: Vec<String>) -> Result<Vec<f32>, JsValue> {
-        let query_vec = self.transform(query)?;
+    #[test]
+    fn test_l1_normalization() {
+        let mut embedder = IncrementalEmbedder::new(2.0);
+        embedder.add_document("今日は天気がいいですね".to_string(), 16).unwrap();
+        embedder.add_document("明日は雨が降りそうです".to_string(), 16).unwrap();
+        embedder.retrain_blocking(16, |_| {}).unwrap();
         
-        candidates.iter()
-            .map(|candidate| {
-                let candidate_vec = self.transform(candidate)?;
-                Ok(cosine_similarity(&query_vec, &candidate_vec))
-            })
-            .collect()
+        embedder.set_normalization(Normalization::L1);
+        let embedding = embedder.transform("今日は雨です").unwrap();
+        assert!((embedding.iter().map(|x| x.abs()).sum::<f32>() - 1.0).abs() < 1e-5);
+        
+        let cached = &embedder.document_vectors[0];
+        assert!((cached.iter().map(|x| x.abs()).sum::<f32>() - 1.0).abs() < 1e-5);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_repeated_terms_keep_tf() {
+        let mut embedder = IncrementalEmbedder::new(2.0);
+        embedder.add_document_for_training("東京と大阪と東京".to_string(), 16).unwrap();
+        embedder.add_document_for_training("明日は雨が降りそうです".to_string(), 16).unwrap();
+        embedder.add_document_for_training("今日は映画を見ました".to_string(), 16).unwrap();
+        
+        let stored = &embedder.tokenized_documents[0];
+        assert_eq!(stored.iter().filter(|t| *t == "東京").count(), 2);
+        assert_eq!(stored.iter().filter(|t| *t == "大阪").count(), 1);
+        
+        // Both terms occur only in the first document, so they share an IDF
+        embedder.retrain_blocking(16, |_| {}).unwrap();
+        let names = embedder.feature_names();
+        let row = &embedder.export_tfidf_matrix()[0];
+        let weight = |term: &str| {
+            let idx = names.iter().position(|name| name == term).unwrap();
+            row.iter().find(|(i, _)| *i == idx).unwrap().1
+        };
+        assert!(weight("東京") > weight("大阪"));
+    }
 
     #[test]
-    fn test_incremental_embedder_basic() {
-        let mut embedder = IncrementalEmbedder::new(0.5); // Higher threshold to avoid auto-retrain
+    fn test_centroid() {
+        let mut embedder = IncrementalEmbedder::new(2.0);
+        for text in [
+            "今日は天気がいいですね",
+            "明日も天気がいいですね",
+            "昨日は映画を見ました",
+            "週末に映画を見ます",
+        ] {
+            embedder.add_document(text.to_string(), 8).unwrap();
+        }
+        embedder.retrain_blocking(8, |_| {}).unwrap();
         
-        // Add documents
-        embedder.add_document("今日は天気がいいですね".to_string(), 64).unwrap();
-        embedder.add_document("明日は雨が降りそうです".to_string(), 64).unwrap();
-        embedder.add_document("今日は映画を見ました".to_string(), 64).unwrap();
+        let centroid = embedder.centroid(&[0, 1]).unwrap();
+        let norm: f32 = centroid.iter().map(|x| x * x).sum::<f32>().sqrt();
+        assert!((norm - 1.0).abs() < 1e-5);
         
-        // Transform a document
-        let embedding = embedder.transform("今日は晴れです").unwrap();
-        assert_eq!(embedding.len(), 64);
+        let vectors = &embedder.document_vectors;
+        for member in [0, 1] {
+            assert!(cosine_similarity(&centroid, &vectors[member]) > cosine_similarity(&centroid, &vectors[2]));
+        }
         
-        // Check similarity
-        let sim = embedder.get_similarity("今日は天気がいい", "明日は天気がいい").unwrap();
-        assert!(sim >= -1.0 && sim <= 1.0);
+        assert!(embedder.centroid(&[0, 4]).is_err());
+        assert!(embedder.centroid(&[]).is_err());
+    }
+
+    #[test]
+    fn test_vocabulary_coverage() {
+        let mut embedder = IncrementalEmbedder::new(2.0);
+        embedder.add_document("今日は天気がいいですね".to_string(), 16).unwrap();
+        embedder.add_document("明日は雨が降りそうです".to_string(), 16).unwrap();
+        embedder.add_document("今日は映画を見ました".to_string(), 16).unwrap();
+        embedder.retrain_blocking(16, |_| {}).unwrap();
+        
+        assert_eq!(embedder.vocabulary_coverage("魑魅魍魎"), 0.0);
+        assert_eq!(embedder.vocabulary_coverage(""), 0.0);
+        assert!(embedder.vocabulary_coverage("明日は雨が降りそうです") > 0.8);
+    }
+
+    #[test]
+    fn test_pause_and_resume_retrain() {
+        let mut embedder = IncrementalEmbedder::new(2.0);
+        for i in 0..5 {
+            embedder.add_document(format!("文書番号{}", i), 8).unwrap();
+        }
+        assert!(embedder.pause_retrain().is_err());
+        
+        embedder.start_background_retrain(8).unwrap();
+        assert!(!embedder.step_retrain().unwrap());
+        let progress = embedder.get_retrain_progress();
+        
+        embedder.pause_retrain().unwrap();
+        assert!(embedder.is_retraining());
+        assert!(embedder.is_paused());
+        assert!(embedder.step_retrain().is_err());
+        
+        // Other work while paused, against the current model
+        embedder.add_document_for_training("今日は天気がいいですね".to_string(), 8).unwrap();
+        embedder.transform("今日は天気").unwrap();
+        assert_eq!(embedder.get_retrain_progress(), progress);
+        
+        embedder.resume_retrain().unwrap();
+        assert!(!embedder.is_paused());
+        while !embedder.step_retrain().unwrap() {}
+        assert!(!embedder.is_retraining());
+        assert!(embedder.get_vocab_size() > 0);
+    }
+
+    #[test]
+    fn test_stable_vocabulary_keeps_indices() {
+        let mut embedder = IncrementalEmbedder::new(2.0);
+        embedder.set_stable_vocabulary(true);
+        embedder.add_document("今日は天気がいいですね".to_string(), 16).unwrap();
+        embedder.add_document("明日は雨が降りそうです".to_string(), 16).unwrap();
+        embedder.add_document("今日は映画を見ました".to_string(), 16).unwrap();
+        embedder.retrain_blocking(16, |_| {}).unwrap();
+        
+        let before = embedder.model.vocabulary().clone();
+        let max_before = *before.values().max().unwrap();
+        
+        embedder.add_document("週末は天気が崩れるらしい".to_string(), 16).unwrap();
+        embedder.add_document("新しい映画館が駅前にできた".to_string(), 16).unwrap();
+        embedder.retrain_blocking(16, |_| {}).unwrap();
+        
+        let after = embedder.model.vocabulary();
+        assert_eq!(after.get("天気"), before.get("天気"));
+        for (token, idx) in after {
+            match before.get(token) {
+                Some(old_idx) => assert_eq!(idx, old_idx),
+                None => assert!(*idx > max_before),
+            }
+        }
+        assert!(after.contains_key("映画館"));
+        assert!(embedder.transform("週末の天気").unwrap().iter().any(|&x| x != 0.0));
+    }
+
+    #[test]
+    fn test_warm_start_after_import() {
+        let mut embedder = IncrementalEmbedder::new(2.0);
+        embedder.add_document("今日は天気がいいですね".to_string(), 16).unwrap();
+        embedder.add_document("明日は雨が降りそうです".to_string(), 16).unwrap();
+        embedder.retrain_blocking(16, |_| {}).unwrap();
+        let vocab_size = embedder.get_vocab_size();
+        
+        let mut imported = IncrementalEmbedder::import_model(&embedder.export_model().unwrap()).unwrap();
+        assert_eq!(imported.get_documents().unwrap(), embedder.get_documents().unwrap());
+        imported.add_document("今日は天気がいいですね".to_string(), 16).unwrap();
+        assert_eq!(imported.get_document_count(), 2);
+        
+        imported.add_document("週末は映画館で新作を見ました".to_string(), 16).unwrap();
+        imported.add_document("駅前の寿司屋が美味しかった".to_string(), 16).unwrap();
+        imported.retrain_blocking(16, |_| {}).unwrap();
+        
+        assert_eq!(imported.get_document_count(), 4);
+        assert!(imported.get_vocab_size() > vocab_size);
+        assert!(imported.model.vocabulary().contains_key("天気"));
+        assert!(imported.model.vocabulary().contains_key("映画"));
+    }
+
+    #[test]
+    fn test_retrain_drift() {
+        let mut embedder = IncrementalEmbedder::new(2.0);
+        for doc in [
+            "今日は天気がいいですね",
+            "明日は雨が降りそうです",
+            "今日は映画を見ました",
+            "天気は晴れです",
+            "映画は面白かったです",
+        ] {
+            embedder.add_document(doc.to_string(), 4).unwrap();
+        }
+        embedder.retrain_blocking(4, |_| {}).unwrap();
+        
+        let previous = embedder.clone();
+        assert!((embedder.retrain_drift(&previous) - 1.0).abs() < 1e-5);
+        
+        // One extra document barely moves the existing ones
+        embedder.add_document("今日は晴れです".to_string(), 4).unwrap();
+        embedder.retrain_blocking(4, |_| {}).unwrap();
+        let drift = embedder.retrain_drift(&previous);
+        assert!(drift > 0.8, "drift {}", drift);
+        
+        assert_eq!(embedder.retrain_drift(&IncrementalEmbedder::new(2.0)), 0.0);
+    }
+
+    #[test]
+    fn test_add_documents_triggers_one_retrain() {
+        let texts: Vec<String> = (0..100).map(|i| format!("一括で追加する文書{}番", i)).collect();
+        
+        let mut one_by_one = IncrementalEmbedder::new(0.5);
+        let mut retrains = 0;
+        for text in texts.clone() {
+            one_by_one.add_document(text, 8).unwrap();
+            if one_by_one.is_retraining() {
+                retrains += 1;
+                while !one_by_one.step_retrain().unwrap() {}
+            }
+        }
+        assert!(retrains > 1);
+        
+        let mut bulk = IncrementalEmbedder::new(0.5);
+        bulk.add_documents(texts.clone(), 8).unwrap();
+        assert!(bulk.is_retraining());
+        while !bulk.step_retrain().unwrap() {}
+        assert_eq!(bulk.get_document_count(), 100);
+        
+        // Duplicates are skipped and nothing changed, so no retrain starts
+        bulk.add_documents(texts, 8).unwrap();
+        assert!(!bulk.is_retraining());
+        assert_eq!(bulk.get_document_count(), 100);
+    }
+
+    #[test]
+    fn test_similar_to_document() {
+        let mut embedder = IncrementalEmbedder::new(2.0);
+        for doc in [
+            "野球の試合で投手が活躍した",
+            "電車が駅に遅れて到着した",
+            "寿司とラーメンが好きです",
+            "サッカーの試合は延長戦になった",
+            "ラーメン屋の新メニューが好評です",
+        ] {
+            embedder.add_document(doc.to_string(), 4).unwrap();
+        }
+        embedder.retrain_blocking(4, |_| {}).unwrap();
+        
+        let similar = embedder.similar_to_document(2, 4).unwrap();
+        assert_eq!(similar.len(), 4);
+        assert!(similar.iter().all(|&(i, _)| i != 2));
+        assert_eq!(similar[0].0, 4);
+        
+        assert!(embedder.similar_to_document(5, 3).is_err());
+    }
+
+    #[test]
+    fn test_single_kanji_query() {
+        let mut embedder = IncrementalEmbedder::new(2.0);
+        for doc in [
+            "夏休みは海水浴に行きました",
+            "電車が駅に遅れて到着した",
+            "寿司とラーメンが好きです",
+            "野球の試合で投手が活躍した",
+        ] {
+            embedder.add_document(doc.to_string(), 4).unwrap();
+        }
+        embedder.retrain_blocking(4, |_| {}).unwrap();
+        
+        let related = embedder.get_similarity("海", "夏休みは海水浴に行きました").unwrap();
+        let unrelated = embedder.get_similarity("海", "電車が駅に遅れて到着した").unwrap();
+        assert!(related > 0.5, "similarity {}", related);
+        assert!(related > unrelated);
+        
+        // Only the kanji itself is added, boosted
+        let tokens = embedder.query_tokens("海");
+        assert_eq!(tokens.iter().filter(|token| *token == "海").count(), SHORT_QUERY_KANJI_BOOST + 1);
+        assert!(tokens.iter().all(|token| token == "海"), "{:?}", tokens);
+        
+        // Diagnostics see the same tokens as transform
+        let diagnosis = embedder.diagnose_transform("海");
+        assert_eq!(diagnosis.total_tokens, tokens.len());
+        let known = diagnosis.in_vocabulary_tokens as f32 / diagnosis.total_tokens as f32;
+        assert_eq!(embedder.vocabulary_coverage("海"), known);
+    }
+
+    #[test]
+    fn test_expand_query() {
+        let mut embedder = IncrementalEmbedder::new(2.0);
+        for doc in [
+            "寿司と刺身を食べた",
+            "刺身も寿司も新鮮だった",
+            "市場で寿司や刺身を買う",
+            "電車が駅に遅れて到着した",
+            "野球の試合で投手が活躍した",
+            "映画館で新作映画を見た",
+            "明日は雨が降りそうです",
+        ] {
+            embedder.add_document(doc.to_string(), 4).unwrap();
+        }
+        assert!(embedder.expand_query("寿司", 5).is_empty());
+        
+        embedder.retrain_blocking(4, |_| {}).unwrap();
+        let expanded = embedder.expand_query("寿司", 5);
+        assert!(expanded.contains(&"寿司".to_string()));
+        assert!(expanded.contains(&"刺身".to_string()), "{:?}", expanded);
+        assert!(!expanded.contains(&"投手".to_string()));
+    }
+
+    #[test]
+    fn test_import_rejects_unknown_format_version() {
+        let mut embedder = IncrementalEmbedder::new(2.0);
+        embedder.add_document("今日は天気がいいですね".to_string(), 4).unwrap();
+        
+        let mut payload: serde_json::Value = serde_json::from_str(&embedder.export_model().unwrap()).unwrap();
+        assert_eq!(payload["format_version"], 1);
+        payload["format_version"] = serde_json::json!(2);
+        
+        let err = IncrementalEmbedder::import_model(&payload.to_string()).err().unwrap();
+        assert!(err.contains("unsupported format version 2 (expected 1)"), "{}", err);
+        
+        // Payloads from before versioning import as version 1
+        payload.as_object_mut().unwrap().remove("format_version");
+        assert!(IncrementalEmbedder::import_model(&payload.to_string()).is_ok());
+    }
+
+    #[test]
+    fn test_diagnose_transform() {
+        let mut embedder = IncrementalEmbedder::new(2.0);
+        for doc in ["今日は天気がいいですね", "明日は雨が降りそうです", "今日は映画を見ました"] {
+            embedder.add_document(doc.to_string(), 4).unwrap();
+        }
+        embedder.retrain_blocking(4, |_| {}).unwrap();
+        
+        let diagnosis = embedder.diagnose_transform("サッカー");
+        assert!(diagnosis.total_tokens > 0);
+        assert_eq!(diagnosis.in_vocabulary_tokens, 0);
+        assert!(diagnosis.lsa_active);
+        assert_eq!(diagnosis.pre_normalization_norm, 0.0);
+        
+        let diagnosis = embedder.diagnose_transform("今日は天気");
+        assert!(diagnosis.in_vocabulary_tokens > 0);
+        assert!(diagnosis.pre_normalization_norm > 0.0);
+    }
+
+    #[test]
+    fn test_transform_pooled() {
+        let mut embedder = IncrementalEmbedder::new(2.0);
+        for doc in [
+            "寿司と刺身を食べた",
+            "新鮮な寿司が美味しい",
+            "野球の試合で投手が活躍した",
+            "野球場で試合を観戦した",
+        ] {
+            embedder.add_document(doc.to_string(), 4).unwrap();
+        }
+        embedder.retrain_blocking(4, |_| {}).unwrap();
+        
+        let text = "寿司と刺身と新鮮な寿司を食べた。野球を見た。";
+        let pooled = embedder.transform_pooled(text).unwrap();
+        let whole = embedder.transform(text).unwrap();
+        assert_eq!(pooled.len(), whole.len());
+        assert!((pooled.iter().map(|x| x * x).sum::<f32>() - 1.0).abs() < 1e-4);
+        assert!(cosine_similarity(&pooled, &whole) < 0.999);
+        
+        // A single sentence pools to its own embedding
+        assert_eq!(embedder.transform_pooled("野球を見た。").unwrap(), embedder.transform("野球を見た。").unwrap());
+        
+        // An out-of-vocabulary sentence adds nothing; the fallback only covers text
+        // with no known term at all
+        let mixed = "野球を見た。魑魅魍魎。";
+        let zero_strategy = embedder.transform_pooled(mixed).unwrap();
+        embedder.set_oov_strategy(OovStrategy::HashFallback);
+        assert_eq!(embedder.transform_pooled(mixed).unwrap(), zero_strategy);
+        let unknown = "魑魅魍魎。鬼哭啾啾。";
+        assert_eq!(embedder.transform_pooled(unknown).unwrap(), embedder.hash_fallback_embedding(unknown));
+        
+        embedder.set_normalization(Normalization::L1);
+        let pooled = embedder.transform_pooled(text).unwrap();
+        assert!((pooled.iter().map(|x| x.abs()).sum::<f32>() - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_assert_dimension_after_import() {
+        let mut embedder = IncrementalEmbedder::new(2.0);
+        for doc in ["今日は天気がいいですね", "明日は雨が降りそうです", "今日は映画を見ました"] {
+            embedder.add_document(doc.to_string(), 4).unwrap();
+        }
+        embedder.retrain_blocking(4, |_| {}).unwrap();
+        
+        let imported = IncrementalEmbedder::import_model(&embedder.export_model().unwrap()).unwrap();
+        assert!(imported.assert_dimension(4).is_ok());
+        let err = imported.assert_dimension(8).err().unwrap();
+        assert!(err.contains("dimension is 4, expected 8"), "{}", err);
+        
+        // Three documents give fewer than 4 LSA components; output is padded
+        assert!(imported.model.output_dim() < 4);
+        assert_eq!(imported.transform("今日は天気").unwrap().len(), 4);
+        assert_eq!(imported.transform("").unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_preprocessor() {
+        let mut embedder = IncrementalEmbedder::new(2.0);
+        assert_ne!(embedder.tokenize("ABCの話"), embedder.tokenize("abcの話"));
+        
+        embedder.set_preprocessor(Box::new(|text: &str| text.to_lowercase()));
+        assert_eq!(embedder.tokenize("ABCの話"), embedder.tokenize("abcの話"));
+        embedder.clear_preprocessor();
+        
+        embedder.set_builtin_preprocessor(Preprocessor::Lowercase);
+        assert_eq!(embedder.tokenize("ABCの話"), embedder.tokenize("abcの話"));
+        
+        // The built-in choice survives export/import
+        let imported = IncrementalEmbedder::import_model(&embedder.export_model().unwrap()).unwrap();
+        assert_eq!(imported.tokenize("ABC"), imported.tokenize("abc"));
+        
+        assert_eq!(Preprocessor::RemoveBrackets.apply("【速報】地震（東京）"), " 速報 地震 東京 ");
+    }
+
+    #[test]
+    fn test_search_returns_texts() {
+        let mut embedder = IncrementalEmbedder::new(2.0);
+        for doc in ["今日は天気がいいですね", "明日は雨が降りそうです", "今日は映画を見ました"] {
+            embedder.add_document(doc.to_string(), 16).unwrap();
+        }
+        embedder.retrain_blocking(16, |_| {}).unwrap();
+        
+        let results = embedder.search("明日は雨", 2).unwrap();
+        let expected = embedder.nearest_documents("明日は雨", 2).unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].0, "明日は雨が降りそうです");
+        for ((text, score), (index, expected_score)) in results.iter().zip(&expected) {
+            assert_eq!(text, &embedder.documents[*index]);
+            assert_eq!(score, expected_score);
+        }
+        
+        embedder.set_store_raw_text(false).unwrap();
+        let err = embedder.search("明日は雨", 2).err().unwrap();
+        assert!(err.contains("store_raw_text"), "{}", err);
+    }
+
+    #[test]
+    fn test_similarity_breakdown() {
+        let mut embedder = IncrementalEmbedder::new(2.0);
+        for doc in ["今日は天気がいいですね", "明日は雨が降りそうです", "今日は映画を見ました"] {
+            embedder.add_document(doc.to_string(), 4).unwrap();
+        }
+        embedder.retrain_blocking(4, |_| {}).unwrap();
+        
+        let breakdown = embedder.similarity_breakdown("今日は天気", "今日は映画").unwrap();
+        assert_eq!(breakdown.len(), embedder.get_embedding_dim());
+        let similarity = embedder.get_similarity("今日は天気", "今日は映画").unwrap();
+        assert!(similarity > 0.0);
+        assert!((breakdown.iter().sum::<f32>() - similarity).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_add_documents_from_lines() {
+        let mut embedder = IncrementalEmbedder::new(2.0);
+        let corpus = "今日は天気がいいですね\n\n  明日は雨が降りそうです  \r\n今日は映画を見ました\n   \n今日は天気がいいですね\n";
+        embedder.add_documents_from_lines(corpus, 16).unwrap();
+        
+        assert_eq!(embedder.get_document_count(), 3);
+        assert_eq!(embedder.documents[1], "明日は雨が降りそうです");
     }
 
     #[test]
@@ -446,6 +2300,21 @@ mod tests {
         assert_eq!(embedder.get_retrain_progress(), 1.0);
     }
 
+    #[test]
+    fn test_retrain_blocking() {
+        let mut embedder = IncrementalEmbedder::new(2.0);
+        for i in 0..5 {
+            embedder.add_document(format!("文書番号{}", i), 32).unwrap();
+        }
+        
+        let mut progress_values = Vec::new();
+        embedder.retrain_blocking(32, |progress| progress_values.push(progress)).unwrap();
+        
+        assert!(!embedder.is_retraining());
+        assert_eq!(progress_values.last(), Some(&1.0));
+        assert!(progress_values.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+
     #[test]
     fn test_model_serialization() {
         let mut embedder = IncrementalEmbedder::new(0.3);
@@ -481,6 +2350,267 @@ mod tests {
         assert_eq!(embedder.get_document_count(), 2); // Should still be 2
     }
     
+    #[test]
+    fn test_document_length_limits() {
+        let mut embedder = IncrementalEmbedder::new(0.5);
+        embedder.set_min_doc_chars(5);
+        embedder.set_max_doc_chars(20);
+        
+        embedder.add_document("は".to_string(), 64).unwrap();
+        embedder.add_document_for_training("はい".to_string(), 64).unwrap();
+        assert_eq!(embedder.get_document_count(), 0);
+        assert_eq!(embedder.get_searchable_count(), 0);
+        
+        embedder.add_document("今日は天気がいいですね".to_string(), 64).unwrap();
+        assert_eq!(embedder.get_document_count(), 1);
+        
+        embedder.add_document("とても長い文書です。".repeat(5), 64).unwrap();
+        assert_eq!(embedder.get_document_count(), 1);
+    }
+    
+    #[test]
+    fn test_tokenizer_configuration_passthrough() {
+        let mut embedder = IncrementalEmbedder::new(2.0);
+        embedder.add_document("AIの研究をしています".to_string(), 32).unwrap();
+        embedder.add_document("MLの勉強をしています".to_string(), 32).unwrap();
+        embedder.add_document("今日は天気がいいですね".to_string(), 32).unwrap();
+        
+        embedder.set_user_dictionary(vec![
+            DictionaryEntry {
+                surface: "人工知能".to_string(),
+                variants: vec!["AI".to_string()],
+                whole_word_only: false,
+            },
+            DictionaryEntry {
+                surface: "機械学習".to_string(),
+                variants: vec!["ML".to_string()],
+                whole_word_only: false,
+            },
+        ]);
+        assert_eq!(embedder.changes_since_update, 4);
+        
+        embedder.start_background_retrain(32).unwrap();
+        while !embedder.step_retrain().unwrap() {}
+        
+        let vocabulary = embedder.model.vocabulary();
+        assert!(vocabulary.contains_key("人工知能"));
+        assert!(vocabulary.contains_key("機械学習"));
+        assert!(embedder.tokenized_documents[0].contains(&"人工知能".to_string()));
+        
+        // Reading the tokenizer is not a change; update_tokenizer is
+        assert_eq!(embedder.tokenizer().min_token_chars(), 1);
+        assert_eq!(embedder.changes_since_update, 0);
+        embedder.update_tokenizer(|tokenizer| tokenizer.set_min_token_chars(2));
+        assert_eq!(embedder.tokenizer().min_token_chars(), 2);
+        assert_eq!(embedder.changes_since_update, 1);
+        assert!(embedder.tokenizer_changed);
+    }
+    
+    #[test]
+    fn test_with_tokenizer() {
+        let tokenizer = JapaneseTokenizer::new_with_ngrams(4, 4);
+        let mut embedder = IncrementalEmbedder::with_tokenizer(0.5, tokenizer);
+        embedder.add_document("今日は天気がいい".to_string(), 32).unwrap();
+        
+        let tokens = &embedder.tokenized_documents[0];
+        assert!(tokens.contains(&"今日は天".to_string()));
+        assert!(!tokens.contains(&"日は".to_string()));
+        
+        // The configured tokenizer survives export/import
+        let restored = IncrementalEmbedder::import_model(&embedder.export_model().unwrap()).unwrap();
+        let mut original_tokens = embedder.tokenizer().tokenize("今日は天気");
+        let mut restored_tokens = restored.tokenizer().tokenize("今日は天気");
+        original_tokens.sort();
+        restored_tokens.sort();
+        assert_eq!(original_tokens, restored_tokens);
+    }
+    
+    #[test]
+    fn test_similarity_matrix() {
+        let mut embedder = IncrementalEmbedder::new(2.0);
+        embedder.add_document("今日は天気がいいですね".to_string(), 32).unwrap();
+        embedder.add_document("明日は雨が降りそうです".to_string(), 32).unwrap();
+        embedder.add_document("今日は映画を見ました".to_string(), 32).unwrap();
+        embedder.start_background_retrain(32).unwrap();
+        while !embedder.step_retrain().unwrap() {}
+        
+        let matrix = embedder.similarity_matrix();
+        assert_eq!(matrix.len(), 3);
+        for (i, row) in matrix.iter().enumerate() {
+            assert!((row[i] - 1.0).abs() < 1e-5);
+            for (j, &value) in row.iter().enumerate() {
+                assert_eq!(value, matrix[j][i]);
+            }
+        }
+    }
+    
+    #[test]
+    fn test_cluster_separates_topics() {
+        let mut embedder = IncrementalEmbedder::new(2.0);
+        let weather = [
+            "今日の天気は晴れです",
+            "明日の天気は晴れです",
+            "週末の天気は晴れです",
+        ];
+        let programming = [
+            "プログラミング言語を勉強する",
+            "新しいプログラミング言語を学ぶ",
+            "プログラミング言語の設計",
+        ];
+        for doc in weather.iter().chain(programming.iter()) {
+            embedder.add_document(doc.to_string(), 4).unwrap();
+        }
+        embedder.start_background_retrain(4).unwrap();
+        while !embedder.step_retrain().unwrap() {}
+        
+        let assignments = embedder.cluster(2, 20);
+        assert_eq!(assignments.len(), 6);
+        assert!(assignments[..3].iter().all(|&a| a == assignments[0]));
+        assert!(assignments[3..].iter().all(|&a| a == assignments[3]));
+        assert_ne!(assignments[0], assignments[3]);
+        
+        // Deterministic seeding gives reproducible assignments
+        assert_eq!(assignments, embedder.cluster(2, 20));
+    }
+    
+    #[test]
+    fn test_merge() {
+        let mut first = IncrementalEmbedder::new(2.0);
+        first.add_document("今日は天気がいいですね".to_string(), 32).unwrap();
+        first.add_document("明日は雨が降りそうです".to_string(), 32).unwrap();
+        
+        let mut second = IncrementalEmbedder::new(2.0);
+        second.add_document("プログラミングを勉強しています".to_string(), 32).unwrap();
+        second.add_document("今日は天気がいいですね".to_string(), 32).unwrap();
+        
+        first.merge(&second).unwrap();
+        assert_eq!(first.get_document_count(), 3);
+        
+        first.retrain_blocking(32, |_| {}).unwrap();
+        let vocabulary = first.model.vocabulary();
+        assert!(vocabulary.contains_key("天気"));
+        assert!(vocabulary.contains_key("プログラミング"));
+        
+        // Incompatible tokenizer settings are rejected
+        let other = IncrementalEmbedder::new_with_ngrams(2.0, 1, 2);
+        assert!(first.merge(&other).is_err());
+    }
+    
+    #[test]
+    fn test_merge_retokenizes_with_own_dictionary() {
+        let mut first = IncrementalEmbedder::new(2.0);
+        first.set_user_dictionary(vec![DictionaryEntry {
+            surface: "人工知能".to_string(),
+            variants: vec!["AI".to_string()],
+            whole_word_only: false,
+        }]);
+        first.set_max_doc_chars(20);
+        
+        let mut second = IncrementalEmbedder::new(2.0);
+        second.add_document_for_training("AIの研究をしています".to_string(), 16).unwrap();
+        second.add_document_for_training("とても長い文章なので最大文字数の制限を超えてしまいます".to_string(), 16).unwrap();
+        assert!(!second.tokenized_documents[0].contains(&"人工知能".to_string()));
+        
+        first.merge(&second).unwrap();
+        assert_eq!(first.get_document_count(), 1);
+        assert_eq!(first.tokenized_documents[0], first.tokenize("AIの研究をしています"));
+        assert!(first.tokenized_documents[0].contains(&"人工知能".to_string()));
+    }
+    
+    #[test]
+    fn test_document_accessors() {
+        let mut embedder = IncrementalEmbedder::new(0.5);
+        embedder.add_document("今日は天気がいいですね".to_string(), 32).unwrap();
+        embedder.add_document_for_training("明日は雨が降りそうです".to_string(), 32).unwrap();
+        
+        assert_eq!(embedder.get_document(0), Some("今日は天気がいいですね"));
+        assert_eq!(embedder.get_document(1), Some("明日は雨が降りそうです"));
+        assert_eq!(embedder.get_document(2), None);
+        assert_eq!(embedder.documents().len(), 2);
+        assert_eq!(embedder.get_documents().unwrap(), embedder.documents());
+    }
+    
+    #[test]
+    fn test_store_raw_text_off() {
+        let mut embedder = IncrementalEmbedder::new(2.0);
+        embedder.add_document_for_training("今日は天気がいいですね".to_string(), 16).unwrap();
+        embedder.set_store_raw_text(false).unwrap();
+        embedder.add_document_for_training("明日は雨が降りそうです".to_string(), 16).unwrap();
+        
+        // Tokens are kept, text is gone
+        assert_eq!(embedder.get_document_count(), 2);
+        assert_eq!(embedder.tokenized_documents.len(), 2);
+        assert!(embedder.tokenized_documents.iter().all(|tokens| !tokens.is_empty()));
+        assert!(embedder.documents().is_empty());
+        assert_eq!(embedder.get_document(0), None);
+        assert!(embedder.get_documents().is_err());
+        
+        // Duplicate detection and training still work
+        assert!(embedder.contains_document("今日は天気がいいですね"));
+        embedder.add_document_for_training("明日は雨が降りそうです".to_string(), 16).unwrap();
+        assert_eq!(embedder.get_document_count(), 2);
+        embedder.retrain_blocking(16, |_| {}).unwrap();
+        assert!(embedder.get_vocab_size() > 0);
+        
+        assert!(embedder.set_store_raw_text(true).is_err());
+    }
+    
+    #[test]
+    fn test_nearest_documents_approx_recall() {
+        let mut embedder = IncrementalEmbedder::new(2.0);
+        let topics = [
+            ["天気", "晴れ", "雨", "気温"],
+            ["映画", "俳優", "監督", "上映"],
+            ["料理", "寿司", "ラーメン", "味付け"],
+            ["電車", "駅", "路線", "時刻表"],
+            ["野球", "投手", "打者", "試合"],
+        ];
+        for (t, words) in topics.iter().enumerate() {
+            for i in 0..12 {
+                let doc = format!("{}と{}について{}回目の話", words[i % 4], words[(i + t + 1) % 4], i);
+                embedder.add_document_for_training(doc, 16).unwrap();
+            }
+        }
+        embedder.retrain_blocking(16, |_| {}).unwrap();
+        embedder.build_ann_index(3);
+        
+        let queries = ["天気と雨", "映画の監督", "寿司とラーメン", "駅の時刻表", "野球の試合"];
+        let mut overlap = 0;
+        for query in queries {
+            let exact: HashSet<usize> = embedder.nearest_documents(query, 5).unwrap().into_iter().map(|(i, _)| i).collect();
+            let approx = embedder.nearest_documents_approx(query, 5).unwrap();
+            overlap += approx.iter().filter(|(i, _)| exact.contains(i)).count();
+        }
+        assert!(overlap as f32 / (queries.len() * 5) as f32 > 0.7, "overlap {}", overlap);
+    }
+    
+    #[test]
+    fn test_ann_index_on_empty_collection() {
+        let mut embedder = IncrementalEmbedder::new(2.0);
+        embedder.build_ann_index(3);
+        let index = embedder.ann_index.as_ref().unwrap();
+        assert_eq!(index.dimension(), embedder.get_embedding_dim());
+        assert!(index.dimension() > 0);
+    }
+    
+    #[test]
+    fn test_export_tfidf_matrix() {
+        let mut embedder = IncrementalEmbedder::new(2.0);
+        embedder.add_document("今日は天気がいいですね".to_string(), 32).unwrap();
+        embedder.add_document("明日は雨が降りそうです".to_string(), 32).unwrap();
+        embedder.add_document("今日は映画を見ました".to_string(), 32).unwrap();
+        embedder.retrain_blocking(32, |_| {}).unwrap();
+        
+        let matrix = embedder.export_tfidf_matrix();
+        let feature_names = embedder.feature_names();
+        assert_eq!(matrix.len(), embedder.get_document_count());
+        assert_eq!(feature_names.len(), embedder.get_vocabulary_size());
+        
+        let tenki = feature_names.iter().position(|name| name == "天気").unwrap();
+        assert!(matrix[0].iter().any(|&(idx, weight)| idx == tenki && weight > 0.0));
+        assert!(matrix[1].iter().all(|&(idx, _)| idx != tenki));
+    }
+    
     #[test]
     fn test_training_vs_searchable_documents() {
         let mut embedder = IncrementalEmbedder::new(0.5);
@@ -530,4 +2660,22 @@ mod tests {
         assert!(results[0].get("document").is_some());
         assert!(results[0].get("score").is_some());
     }
+    
+    #[test]
+    fn test_nearest_documents_json() {
+        let mut embedder = IncrementalEmbedder::new(2.0);
+        embedder.add_document("今日は天気がいいですね".to_string(), 16).unwrap();
+        embedder.add_document("明日は雨が降りそうです".to_string(), 16).unwrap();
+        embedder.add_document("今日は映画を見ました".to_string(), 16).unwrap();
+        embedder.retrain_blocking(16, |_| {}).unwrap();
+        
+        let results_json = embedder.nearest_documents_json("明日は雨", 2).unwrap();
+        let results: Vec<serde_json::Value> = serde_json::from_str(&results_json).unwrap();
+        let expected = embedder.nearest_documents("明日は雨", 2).unwrap();
+        
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0]["index"], 1);
+        assert_eq!(results[0]["index"].as_u64().unwrap() as usize, expected[0].0);
+        assert!((results[0]["score"].as_f64().unwrap() as f32 - expected[0].1).abs() < 1e-6);
+    }
 }
\ No newline at end of file
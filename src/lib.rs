@@ -10,11 +10,16 @@ pub mod tokenizer;
 pub mod tfidf_lsa;
 pub mod incremental;
 pub mod stable_hash;
+pub mod ann;
+pub mod classifier;
+pub mod embedder;
 pub mod utils;
 
 // Re-export main types
-pub use incremental::IncrementalEmbedder;
+pub use incremental::{IncrementalEmbedder, OovStrategy, Preprocessor, TransformDiagnosis};
 pub use stable_hash::StableHashEmbedder;
+pub use embedder::Embedder;
+pub use utils::Normalization;
 
 // Set up console error panic hook for better debugging in browser
 #[cfg_attr(target_arch = "wasm32", wasm_bindgen(start))]
@@ -1,18 +1,28 @@
-use crate::tokenizer::{JapaneseTokenizer, DictionaryEntry};
-use crate::utils::l2_normalize;
-use std::collections::hash_map::DefaultHasher;
-use std::hash::{Hash, Hasher};
+use crate::incremental::create_error;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::incremental::JsValue;
+use crate::tokenizer::{is_segment_boundary, JapaneseTokenizer, DictionaryEntry};
+use crate::utils::{fnv1a_64, normalize, Normalization};
+use serde::{Deserialize, Serialize};
 use serde_json;
+use std::collections::HashMap;
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
 
 #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StableHashEmbedder {
     dimension: usize,
     char_ngram_size: usize,
     seed: u64,
     tokenizer: JapaneseTokenizer,
+    normalization: Normalization,
+    // N-grams never span whitespace/punctuation (false restores the old behavior
+    // of dropping whitespace and hashing n-grams across it)
+    segment_boundaries: bool,
+    // Per-token multipliers for hashed n-grams/tokens (missing tokens weigh 1.0)
+    #[serde(default)]
+    weights: HashMap<String, f32>,
 }
 
 #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
@@ -24,6 +34,9 @@ impl StableHashEmbedder {
             char_ngram_size,
             seed: 42, // Fixed seed for stability
             tokenizer: JapaneseTokenizer::new(),
+            normalization: Normalization::L2,
+            segment_boundaries: true,
+            weights: HashMap::new(),
         }
     }
 
@@ -34,32 +47,45 @@ impl StableHashEmbedder {
             char_ngram_size,
             seed,
             tokenizer: JapaneseTokenizer::new(),
+            normalization: Normalization::L2,
+            segment_boundaries: true,
+            weights: HashMap::new(),
         }
     }
 
+    // Empty or whitespace-only text explicitly maps to the all-zero vector
+    // (see `utils::is_zero_vector`) instead of the hash of an empty string
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
     pub fn transform(&self, text: &str) -> Vec<f32> {
         let mut embedding = vec![0.0f32; self.dimension];
         
+        if text.trim().is_empty() {
+            return embedding;
+        }
+        
         // Use tokenizer if dictionary is present
         if self.tokenizer.user_dictionary.is_some() {
             let tokens = self.tokenizer.tokenize(text);
             for token in tokens {
                 self.hash_and_accumulate(&token, &mut embedding);
             }
+        } else if self.segment_boundaries {
+            // Character n-grams within each whitespace/punctuation-delimited segment
+            let segments: Vec<&str> = text.split(is_segment_boundary).filter(|s| !s.is_empty()).collect();
+            if segments.is_empty() {
+                self.hash_and_accumulate(text, &mut embedding);
+            }
+            for segment in segments {
+                self.hash_char_ngrams(segment, &mut embedding);
+            }
         } else {
             // Generate character n-grams (original behavior)
-            let chars: Vec<char> = text.chars().filter(|c| !c.is_whitespace()).collect();
-            
-            if chars.len() < self.char_ngram_size {
+            let chars: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+            if chars.chars().count() < self.char_ngram_size {
                 // Handle short texts
-                self.hash_and_accumulate(&text, &mut embedding);
+                self.hash_and_accumulate(text, &mut embedding);
             } else {
-                // Generate n-grams
-                for i in 0..=chars.len() - self.char_ngram_size {
-                    let ngram: String = chars[i..i + self.char_ngram_size].iter().collect();
-                    self.hash_and_accumulate(&ngram, &mut embedding);
-                }
+                self.hash_char_ngrams(&chars, &mut embedding);
             }
         }
         
@@ -67,12 +93,30 @@ impl StableHashEmbedder {
         self.add_char_type_features(text, &mut embedding);
         
         // Normalize the embedding
-        l2_normalize(&mut embedding);
+        normalize(&mut embedding, self.normalization);
         
         embedding
     }
 
+    // Hash every char_ngram_size window of text, or the whole text when it's shorter
+    fn hash_char_ngrams(&self, text: &str, embedding: &mut [f32]) {
+        let chars: Vec<char> = text.chars().collect();
+        
+        if chars.len() < self.char_ngram_size {
+            // Handle short texts
+            self.hash_and_accumulate(text, embedding);
+        } else {
+            // Generate n-grams
+            for i in 0..=chars.len() - self.char_ngram_size {
+                let ngram: String = chars[i..i + self.char_ngram_size].iter().collect();
+                self.hash_and_accumulate(&ngram, embedding);
+            }
+        }
+    }
+
     fn hash_and_accumulate(&self, token: &str, embedding: &mut [f32]) {
+        let weight = self.weights.get(token).copied().unwrap_or(1.0);
+        
         // Use multiple hash functions for better distribution
         for hash_idx in 0..3 {
             let hash_value = self.hash_token(token, hash_idx);
@@ -80,16 +124,18 @@ impl StableHashEmbedder {
             
             // Use hash value to determine sign (feature hashing trick)
             let sign = if hash_value & 1 == 0 { 1.0 } else { -1.0 };
-            embedding[index] += sign;
+            embedding[index] += sign * weight;
         }
     }
 
+    // FNV-1a over little-endian seed/index bytes and the UTF-8 token, so hashes
+    // are reproducible across toolchains and platforms
     fn hash_token(&self, token: &str, hash_idx: u32) -> u64 {
-        let mut hasher = DefaultHasher::new();
-        self.seed.hash(&mut hasher);
-        hash_idx.hash(&mut hasher);
-        token.hash(&mut hasher);
-        hasher.finish()
+        fnv1a_64(&[
+            &self.seed.to_le_bytes(),
+            &hash_idx.to_le_bytes(),
+            token.as_bytes(),
+        ])
     }
 
     fn add_char_type_features(&self, text: &str, embedding: &mut [f32]) {
@@ -145,11 +191,57 @@ impl StableHashEmbedder {
         self.dimension
     }
 
+    // Whether character n-grams stop at whitespace and punctuation (default true).
+    // Pass false to reproduce embeddings from versions that hashed across them.
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn set_segment_boundaries(&mut self, enabled: bool) {
+        self.segment_boundaries = enabled;
+    }
+    
+    // L2 (default) or L1 scaling of transform output
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn set_normalization(&mut self, normalization: Normalization) {
+        self.normalization = normalization;
+    }
+    
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
     pub fn get_ngram_size(&self) -> usize {
         self.char_ngram_size
     }
     
+    // Full state, including the token weights
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn to_json(&self) -> Result<String, JsValue> {
+        serde_json::to_string(self)
+            .map_err(|e| create_error(&format!("Failed to export embedder: {}", e)))
+    }
+    
+    // Settings only, leaving out the (possibly large) weights map. Reattach the
+    // weights after import with set_weights_json.
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn to_json_without_weights(&self) -> Result<String, JsValue> {
+        let config = StableHashEmbedder {
+            tokenizer: self.tokenizer.clone(),
+            weights: HashMap::new(),
+            ..*self
+        };
+        config.to_json()
+    }
+    
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn from_json(json_data: &str) -> Result<StableHashEmbedder, JsValue> {
+        serde_json::from_str(json_data)
+            .map_err(|e| create_error(&format!("Failed to import embedder: {}", e)))
+    }
+    
+    // Replace the token weights with a JSON object of token -> weight
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+    pub fn set_weights_json(&mut self, weights_json: &str) -> Result<(), JsValue> {
+        self.weights = serde_json::from_str(weights_json)
+            .map_err(|e| create_error(&format!("Failed to parse weights: {}", e)))?;
+        Ok(())
+    }
+    
     #[cfg(target_arch = "wasm32")]
     #[wasm_bindgen]
     pub fn set_dictionary(&mut self, dictionary_json: &str) -> Result<(), JsValue> {
@@ -177,6 +269,14 @@ impl StableHashEmbedder {
 
 // Non-WASM methods for internal use
 impl StableHashEmbedder {
+    pub fn set_weights(&mut self, weights: HashMap<String, f32>) {
+        self.weights = weights;
+    }
+    
+    pub fn weights(&self) -> &HashMap<String, f32> {
+        &self.weights
+    }
+    
     pub fn transform_batch(&self, texts: Vec<String>) -> Vec<Vec<f32>> {
         texts.iter()
             .map(|text| self.transform(text))
@@ -257,6 +357,61 @@ mod tests {
         assert!(sum > 0.0);
     }
 
+    #[test]
+    fn test_empty_input_yields_zero_vector() {
+        let embedder = StableHashEmbedder::new(32, 2);
+        
+        for text in ["", "   "] {
+            let embedding = embedder.transform(text);
+            assert_eq!(embedding.len(), 32);
+            assert!(crate::utils::is_zero_vector(&embedding));
+            assert_eq!(embedder.get_similarity(text, "今日は天気がいい"), 0.0);
+        }
+    }
+
+    #[test]
+    fn test_ngrams_respect_segment_boundaries() {
+        let mut embedder = StableHashEmbedder::new(64, 2);
+        let joined = embedder.get_similarity("東京大阪", "東京 大阪");
+        assert!(joined < 0.9);
+        
+        // Legacy behavior drops the space and hashes "京大" either way
+        embedder.set_segment_boundaries(false);
+        let legacy = embedder.get_similarity("東京大阪", "東京 大阪");
+        assert!(legacy > 0.99);
+        assert!(legacy > joined);
+    }
+
+    #[test]
+    fn test_weights_round_trip() {
+        let mut embedder = StableHashEmbedder::new(64, 2);
+        let text = "今日は天気がいい";
+        let unweighted = embedder.transform(text);
+        embedder.set_weights(HashMap::from([("天気".to_string(), 3.0), ("今日".to_string(), 0.5)]));
+        let weighted = embedder.transform(text);
+        assert_ne!(weighted, unweighted);
+        
+        let restored = StableHashEmbedder::from_json(&embedder.to_json().unwrap()).unwrap();
+        assert_eq!(restored.transform(text), weighted);
+        
+        // Config-only export needs the weights reattached
+        let mut config_only = StableHashEmbedder::from_json(&embedder.to_json_without_weights().unwrap()).unwrap();
+        assert!(config_only.weights().is_empty());
+        assert_eq!(config_only.transform(text), unweighted);
+        config_only.set_weights_json(&serde_json::to_string(embedder.weights()).unwrap()).unwrap();
+        assert_eq!(config_only.transform(text), weighted);
+    }
+
+    #[test]
+    fn test_hash_values_are_locked() {
+        let embedder = StableHashEmbedder::new(64, 2);
+        assert_eq!(embedder.hash_token("天気", 0), 0xb5b5_f684_2511_d624);
+        assert_eq!(embedder.hash_token("天気", 1), 0x8bdf_3638_3218_8589);
+        
+        let embedder = StableHashEmbedder::new_with_seed(64, 2, 7);
+        assert_eq!(embedder.hash_token("天気", 0), 0x94fc_c1ed_7c52_8e7d);
+    }
+
     #[test]
     fn test_different_seeds() {
         let embedder1 = StableHashEmbedder::new_with_seed(32, 2, 42);
@@ -1,34 +1,224 @@
 use nalgebra::{DMatrix, DVector};
 use nalgebra::linalg::SVD;
+use crate::tokenizer::JapaneseTokenizer;
+use crate::utils::{cosine_similarity, default_format_version, is_zero_vector, l2_normalize, read_format_version, SeededRng, FORMAT_VERSION};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+
+// How perform_lsa obtains the term-space singular vectors
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum LsaSolver {
+    // Whichever of the two factorizes the smaller matrix: Gram when there are
+    // fewer documents than terms, SVD otherwise
+    #[default]
+    Auto,
+    // Thin SVD of the terms x documents matrix
+    Svd,
+    // Eigendecomposition of the documents x documents Gram matrix XᵀX. Squares the
+    // condition number, so weak singular values are lost (see gram_decomposition).
+    Gram,
+}
+
+// Model settings, serialized inline with the model
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TfIdfLsaConfig {
+    pub embedding_dim: usize,
+    // Use term presence (1.0) instead of raw counts for TF
+    pub binary_tf: bool,
+    // Multiplier applied to the TF-IDF weight of boosted (dictionary) terms
+    pub dictionary_boost: f32,
+    // Divide projected components by their singular values (classic Σ⁻¹Uᵀq query
+    // projection). Every latent dimension then contributes on a comparable scale,
+    // at the cost of amplifying weak, noisier dimensions.
+    pub scale_by_singular_values: bool,
+    // Token i of a document contributes 1 / (1 + i * position_decay) to its TF, so
+    // earlier tokens weigh more (0 disables). Tokens must be in document order,
+    // e.g. from JapaneseTokenizer::tokenize_ordered.
+    pub position_decay: f32,
+    // L2-normalize the output of transform. IncrementalEmbedder normalizes on its
+    // own (see Normalization), so this only matters for standalone use; enabling
+    // it there is harmless since normalizing a unit vector again is a no-op.
+    pub normalize_output: bool,
+    pub lsa_solver: LsaSolver,
+    // Keep the terms x terms covariance XXᵀ of the last fit in memory so that
+    // update_with_documents can fold in new documents without rebuilding it.
+    // Costs vocabulary² floats.
+    pub cache_covariance: bool,
+    // Lower bound on the IDF of terms that occur in the corpus, so terms found in
+    // (nearly) every document keep some weight instead of vanishing (0 disables)
+    pub idf_floor: f32,
+    // LSA only engages once the model has this many documents (at least 2); until
+    // then transform returns truncated TF-IDF, avoiding the unstable components
+    // of a tiny corpus
+    pub min_docs_for_lsa: usize,
+}
+
+impl Default for TfIdfLsaConfig {
+    fn default() -> Self {
+        Self {
+            embedding_dim: 64,
+            binary_tf: false,
+            dictionary_boost: 1.0,
+            scale_by_singular_values: false,
+            position_decay: 0.0,
+            normalize_output: false,
+            lsa_solver: LsaSolver::Auto,
+            cache_covariance: false,
+            idf_floor: 0.0,
+            min_docs_for_lsa: 2,
+        }
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TfIdfLsa {
+    #[serde(flatten)]
+    config: TfIdfLsaConfig,
     vocabulary: HashMap<String, usize>,
     idf_weights: Vec<f32>,
+    #[serde(default)]
+    doc_freq: Vec<usize>,
     lsa_components: Option<DMatrix<f32>>,
-    embedding_dim: usize,
+    // Singular value of each LSA component (0.0 for discarded directions)
+    #[serde(default)]
+    singular_values: Vec<f32>,
+    // Eigenvalues σ² of the covariance XXᵀ from the last decomposition, including
+    // the directions beyond embedding_dim
+    #[serde(default)]
+    eigenvalue_spectrum: Vec<f32>,
     documents_count: usize,
+    // Terms weighted by config.dictionary_boost
+    #[serde(default)]
+    boosted_terms: HashSet<String>,
+    // Denylisted terms whose IDF is kept at zero
+    #[serde(default)]
+    excluded_tokens: HashSet<String>,
+    // Layout version of the JSON export, checked by from_json
+    #[serde(default = "default_format_version")]
+    format_version: u32,
+    // XXᵀ of the documents fitted so far (see config.cache_covariance)
+    #[serde(skip)]
+    covariance: Option<CachedCovariance>,
+}
+
+// Covariance kept by config.cache_covariance, with the number of documents it sums
+// over (the fitted ones plus those added by update_with_documents)
+#[derive(Debug, Clone)]
+struct CachedCovariance {
+    matrix: DMatrix<f32>,
+    documents: usize,
 }
 
 impl TfIdfLsa {
     pub fn new(embedding_dim: usize) -> Self {
+        Self::with_config(TfIdfLsaConfig {
+            embedding_dim,
+            ..TfIdfLsaConfig::default()
+        })
+    }
+    
+    pub fn with_config(config: TfIdfLsaConfig) -> Self {
         Self {
+            config,
             vocabulary: HashMap::new(),
             idf_weights: Vec::new(),
+            doc_freq: Vec::new(),
             lsa_components: None,
-            embedding_dim,
+            singular_values: Vec::new(),
+            eigenvalue_spectrum: Vec::new(),
             documents_count: 0,
+            boosted_terms: HashSet::new(),
+            excluded_tokens: HashSet::new(),
+            format_version: FORMAT_VERSION,
+            covariance: None,
         }
     }
+    
+    pub fn config(&self) -> &TfIdfLsaConfig {
+        &self.config
+    }
 
     // Build TF-IDF matrix from documents
     pub fn fit(&mut self, documents: &[Vec<String>], vocabulary: HashMap<String, usize>) {
+        self.fit_matrix(documents, vocabulary);
+    }
+    
+    // Fit, then return the L2-normalized embedding of each training document,
+    // projected from the TF-IDF matrix fit just built (same values as transform)
+    pub fn fit_transform(&mut self, documents: &[Vec<String>], vocabulary: HashMap<String, usize>) -> Vec<Vec<f32>> {
+        let tfidf_matrix = self.fit_matrix(documents, vocabulary);
+        self.project_columns(&tfidf_matrix)
+    }
+    
+    // L2-normalized embeddings of many tokenized documents (same values as
+    // transform followed by l2_normalize), projected with a single matrix multiply
+    pub fn transform_matrix(&self, token_lists: &[Vec<String>]) -> Vec<Vec<f32>> {
+        let mut tfidf_matrix = DMatrix::zeros(self.feature_count(), token_lists.len());
+        for (doc_idx, tokens) in token_lists.iter().enumerate() {
+            for (term_idx, tfidf) in self.tfidf_vector(tokens).into_iter().enumerate() {
+                if tfidf != 0.0 {
+                    tfidf_matrix[(term_idx, doc_idx)] = tfidf;
+                }
+            }
+        }
+        self.project_columns(&tfidf_matrix)
+    }
+    
+    // Embed every column of a TF-IDF matrix (terms x documents) and L2-normalize it
+    fn project_columns(&self, tfidf_matrix: &DMatrix<f32>) -> Vec<Vec<f32>> {
+        let mut embeddings: Vec<Vec<f32>> = match self.lsa_components {
+            Some(ref components) => (components * tfidf_matrix)
+                .column_iter()
+                .map(|column| {
+                    let mut embedded: Vec<f32> = column.iter().cloned().collect();
+                    self.scale_projection(&mut embedded);
+                    embedded
+                })
+                .collect(),
+            None => tfidf_matrix
+                .column_iter()
+                .map(|column| {
+                    let mut tfidf_vec: Vec<f32> = column.iter().cloned().collect();
+                    tfidf_vec.truncate(self.config.embedding_dim);
+                    tfidf_vec.resize(self.config.embedding_dim, 0.0);
+                    tfidf_vec
+                })
+                .collect(),
+        };
+        
+        for embedding in &mut embeddings {
+            l2_normalize(embedding);
+        }
+        embeddings
+    }
+    
+    // Tokenize raw documents, build the vocabulary and fit in one call. Dictionary
+    // terms of the tokenizer are boosted by config.dictionary_boost. With
+    // position_decay, tokens are kept in document order.
+    pub fn fit_raw(&mut self, documents: &[String], tokenizer: &JapaneseTokenizer) {
+        let tokenize = |doc: &String| {
+            if self.config.position_decay > 0.0 {
+                tokenizer.tokenize_ordered(doc)
+            } else {
+                tokenizer.tokenize(doc)
+            }
+        };
+        let tokenized_docs: Vec<Vec<String>> = documents.iter().map(tokenize).collect();
+        let vocabulary = tokenizer.build_vocabulary_from_tokens(&tokenized_docs);
+        self.set_boosted_terms(tokenizer.dictionary_terms());
+        self.fit(&tokenized_docs, vocabulary);
+    }
+    
+    // Shared by fit and fit_transform: returns the TF-IDF matrix (terms x documents)
+    fn fit_matrix(&mut self, documents: &[Vec<String>], vocabulary: HashMap<String, usize>) -> DMatrix<f32> {
         self.vocabulary = vocabulary;
         self.documents_count = documents.len();
+        self.lsa_components = None;
+        self.singular_values.clear();
+        self.eigenvalue_spectrum.clear();
         
-        let vocab_size = self.vocabulary.len();
+        let vocab_size = self.feature_count();
         
         // Calculate document frequencies
         let mut doc_freq = vec![0usize; vocab_size];
@@ -45,91 +235,315 @@ impl TfIdfLsa {
         }
         
         // Calculate IDF weights
-        self.idf_weights = doc_freq
+        self.doc_freq = doc_freq;
+        self.recompute_idf_weights();
+        
+        // Build TF-IDF matrix
+        let mut tfidf_matrix = DMatrix::zeros(vocab_size, self.documents_count);
+        
+        for (doc_idx, doc_tokens) in documents.iter().enumerate() {
+            let tfidf_vec = self.tfidf_vector(doc_tokens);
+            for (term_idx, &tfidf) in tfidf_vec.iter().enumerate() {
+                if tfidf != 0.0 {
+                    tfidf_matrix[(term_idx, doc_idx)] = tfidf;
+                }
+            }
+        }
+        
+        // Perform LSA using SVD. With fewer than min_docs_for_lsa documents or a
+        // vocabulary smaller than embedding_dim, LSA stays inactive and transform falls back to the raw
+        // TF-IDF weights of the first min(vocab, dim) terms, zero-padded to dim.
+        if self.has_enough_data_for_lsa(self.documents_count) {
+            self.perform_lsa(&tfidf_matrix);
+        }
+        
+        if self.config.cache_covariance {
+            let matrix = tfidf_matrix.map(|x| if x.is_finite() { x } else { 0.0 });
+            self.covariance = Some(CachedCovariance {
+                matrix: &matrix * matrix.transpose(),
+                documents: self.documents_count,
+            });
+        }
+        
+        tfidf_matrix
+    }
+    
+    // Add documents to a model fitted with config.cache_covariance by adding their
+    // outer products to the cached covariance and re-deriving the LSA components
+    // from it by block power iteration, warm-started from the current components,
+    // instead of rebuilding and decomposing the TF-IDF matrix of every document.
+    // The vocabulary, document frequencies and IDF weights stay as of the last fit
+    // (unseen terms are ignored) so the cache stays exact; the next full fit takes
+    // the added documents into account. Returns false without changing anything
+    // when no covariance is cached (caching disabled, or invalidated since the last
+    // fit by fold_in or exclude_tokens).
+    pub fn update_with_documents(&mut self, documents: &[Vec<String>]) -> bool {
+        let Some(mut covariance) = self.covariance.take() else {
+            return false;
+        };
+        
+        for doc_tokens in documents {
+            let tfidf = DVector::from_iterator(
+                covariance.matrix.nrows(),
+                self.tfidf_vector(doc_tokens).into_iter().map(|x| if x.is_finite() { x } else { 0.0 }),
+            );
+            covariance.matrix.ger(1.0, &tfidf, &tfidf, 1.0);
+        }
+        covariance.documents += documents.len();
+        
+        if self.has_enough_data_for_lsa(covariance.documents) {
+            let target_dim = self.config.embedding_dim.min(covariance.matrix.nrows()).min(covariance.documents);
+            let warm_start = self.lsa_components.as_ref();
+            if let Some(decomposition) = covariance_decomposition(&covariance.matrix, target_dim, warm_start) {
+                self.set_lsa_components(decomposition, target_dim);
+            }
+        }
+        self.covariance = Some(covariance);
+        true
+    }
+    
+    // The cached covariance XXᵀ, if config.cache_covariance is set and it is current
+    pub fn covariance(&self) -> Option<&DMatrix<f32>> {
+        self.covariance.as_ref().map(|covariance| &covariance.matrix)
+    }
+    
+    fn has_enough_data_for_lsa(&self, documents_count: usize) -> bool {
+        documents_count >= self.config.min_docs_for_lsa.max(2)
+            && self.vocabulary.len() >= self.config.embedding_dim
+    }
+    
+    // Build the TF-IDF vector (indexed by vocabulary) for a tokenized document
+    fn tfidf_vector(&self, tokens: &[String]) -> Vec<f32> {
+        let vocab_size = self.feature_count();
+        let mut tf_counts = vec![0f32; vocab_size];
+        
+        // Count term frequencies (presence only in binary mode), scaling boosted terms
+        // and decaying by position
+        for (position, token) in tokens.iter().enumerate() {
+            if let Some(&idx) = self.vocabulary.get(token) {
+                let mut weight = if self.boosted_terms.contains(token) {
+                    self.config.dictionary_boost
+                } else {
+                    1.0
+                };
+                if self.config.position_decay > 0.0 {
+                    weight /= 1.0 + position as f32 * self.config.position_decay;
+                }
+                if self.config.binary_tf {
+                    tf_counts[idx] = tf_counts[idx].max(weight);
+                } else {
+                    tf_counts[idx] += weight;
+                }
+            }
+        }
+        
+        // Normalize TF and apply IDF
+        let total_terms = if self.config.binary_tf {
+            tokens.iter().collect::<HashSet<_>>().len() as f32
+        } else {
+            tokens.len() as f32
+        };
+        if total_terms > 0.0 {
+            for (idx, count) in tf_counts.iter_mut().enumerate() {
+                if *count > 0.0 && idx < self.idf_weights.len() {
+                    *count = *count / total_terms * self.idf_weights[idx];
+                }
+            }
+        }
+        
+        tf_counts
+    }
+    
+    // Sparse TF-IDF vector of a tokenized document as (vocabulary index, weight)
+    // pairs in ascending index order
+    pub fn tfidf_sparse(&self, tokens: &[String]) -> Vec<(usize, f32)> {
+        self.tfidf_vector(tokens)
+            .into_iter()
+            .enumerate()
+            .filter(|(_, weight)| *weight != 0.0)
+            .collect()
+    }
+    
+    // Number of TF-IDF features: one past the highest vocabulary index. Larger than
+    // the vocabulary when a stable vocabulary left unused indices.
+    fn feature_count(&self) -> usize {
+        self.vocabulary.values().max().map_or(0, |&idx| idx + 1)
+    }
+    
+    // Vocabulary tokens ordered by index (the column names of the TF-IDF matrix).
+    // Unused indices are empty strings.
+    pub fn feature_names(&self) -> Vec<String> {
+        let mut names = vec![String::new(); self.feature_count()];
+        for (token, &idx) in &self.vocabulary {
+            if idx < names.len() {
+                names[idx] = token.clone();
+            }
+        }
+        names
+    }
+    
+    // Recompute IDF weights from the stored document frequencies. This changes every
+    // TF-IDF column, so the cached covariance no longer applies.
+    fn recompute_idf_weights(&mut self) {
+        self.covariance = None;
+        let documents_count = self.documents_count as f32;
+        self.idf_weights = self.doc_freq
             .iter()
             .map(|&df| {
                 if df > 0 {
-                    ((self.documents_count as f32 + 1.0) / (df as f32 + 1.0)).ln()
+                    ((documents_count + 1.0) / (df as f32 + 1.0)).ln().max(self.config.idf_floor)
                 } else {
                     0.0
                 }
             })
             .collect();
         
-        // Build TF-IDF matrix
-        let mut tfidf_matrix = DMatrix::zeros(vocab_size, self.documents_count);
-        
-        for (doc_idx, doc_tokens) in documents.iter().enumerate() {
-            // Calculate term frequencies
-            let mut tf_counts = vec![0f32; vocab_size];
-            for token in doc_tokens {
-                if let Some(&idx) = self.vocabulary.get(token) {
-                    tf_counts[idx] += 1.0;
+        for token in &self.excluded_tokens {
+            if let Some(&idx) = self.vocabulary.get(token) {
+                if let Some(idf) = self.idf_weights.get_mut(idx) {
+                    *idf = 0.0;
                 }
             }
-            
-            // Normalize TF and apply IDF
-            let total_terms = doc_tokens.len() as f32;
-            for (term_idx, &count) in tf_counts.iter().enumerate() {
-                if count > 0.0 {
-                    let tf = count / total_terms;
-                    let tfidf = tf * self.idf_weights[term_idx];
-                    tfidf_matrix[(term_idx, doc_idx)] = tfidf;
+        }
+    }
+    
+    // Remove noisy tokens' contribution without re-tokenizing: their IDF is zeroed
+    // now and stays zero after fold_in and later fits. The LSA components are left
+    // as they are, so refit to also take them out of the latent space.
+    pub fn exclude_tokens(&mut self, tokens: &[String]) {
+        self.excluded_tokens.extend(tokens.iter().cloned());
+        self.recompute_idf_weights();
+    }
+    
+    // Register one more document without a full refit (folding-in). Document
+    // frequencies and IDF weights are updated and unseen tokens are appended to the
+    // vocabulary, but the LSA components stay fixed (new terms get zero loadings).
+    // The latent space therefore drifts from what a full refit would produce as
+    // more documents are folded in, so a periodic fit is still recommended.
+    pub fn fold_in(&mut self, tokens: &[String]) {
+        // Models imported from before doc_freq was stored: recover it from the IDF
+        if self.doc_freq.len() != self.idf_weights.len() {
+            let documents_count = self.documents_count as f32;
+            self.doc_freq = self.idf_weights
+                .iter()
+                .map(|&idf| {
+                    if idf > 0.0 {
+                        ((documents_count + 1.0) / idf.exp() - 1.0).round().max(0.0) as usize
+                    } else {
+                        0
+                    }
+                })
+                .collect();
+        }
+        
+        // Sorted so new tokens get the same indices regardless of input order
+        let mut unique_tokens: Vec<&String> = tokens.iter().collect::<HashSet<_>>().into_iter().collect();
+        unique_tokens.sort();
+        for token in unique_tokens {
+            match self.vocabulary.get(token) {
+                Some(&idx) => self.doc_freq[idx] += 1,
+                None => {
+                    self.vocabulary.insert(token.clone(), self.doc_freq.len());
+                    self.doc_freq.push(1);
+                    if let Some(components) = self.lsa_components.take() {
+                        let ncols = components.ncols();
+                        self.lsa_components = Some(components.resize_horizontally(ncols + 1, 0.0));
+                    }
                 }
             }
         }
         
-        // Perform LSA using SVD
-        if self.documents_count >= 2 && vocab_size >= self.embedding_dim {
-            self.perform_lsa(tfidf_matrix);
+        self.documents_count += 1;
+        self.recompute_idf_weights();
+    }
+    
+    // Whether perform_lsa decomposes a terms x documents matrix of this shape via
+    // the Gram matrix
+    fn uses_gram_solver(&self, nrows: usize, ncols: usize) -> bool {
+        match self.config.lsa_solver {
+            LsaSolver::Auto => decomposed_elements(true, nrows, ncols) < decomposed_elements(false, nrows, ncols),
+            LsaSolver::Svd => false,
+            LsaSolver::Gram => true,
         }
     }
     
     // Perform Latent Semantic Analysis using SVD
-    fn perform_lsa(&mut self, tfidf_matrix: DMatrix<f32>) {
+    fn perform_lsa(&mut self, tfidf_matrix: &DMatrix<f32>) {
         let (nrows, ncols) = tfidf_matrix.shape();
-        let target_dim = self.embedding_dim.min(nrows).min(ncols);
+        let target_dim = self.config.embedding_dim.min(nrows).min(ncols);
         
-        // Perform Singular Value Decomposition (SVD)
-        // TF-IDF matrix = U * Σ * V^T
-        // Where U contains left singular vectors (document-concept relationships)
-        // Σ contains singular values (importance of each concept)
-        // V^T contains right singular vectors (term-concept relationships)
-        let svd = SVD::new(tfidf_matrix.clone(), true, true);
+        // Non-finite weights would poison the whole decomposition
+        let matrix = tfidf_matrix.map(|x| if x.is_finite() { x } else { 0.0 });
+        let decomposition = if self.uses_gram_solver(nrows, ncols) {
+            gram_decomposition(&matrix)
+        } else {
+            svd_decomposition(matrix)
+        };
         
         // Extract U matrix (left singular vectors)
-        if let Some(u_matrix) = svd.u {
-            // Select top k components from U matrix
-            // These represent the most important latent semantic dimensions
-            let mut components = DMatrix::zeros(target_dim, nrows);
-            
-            // Copy the first target_dim columns of U^T
-            // We transpose because we want each row to be a component
-            for i in 0..target_dim {
-                for j in 0..nrows {
-                    components[(i, j)] = u_matrix[(j, i)];
-                }
+        if let Some(decomposition) = decomposition {
+            self.set_lsa_components(decomposition, target_dim);
+        }
+        // If SVD fails, LSA stays inactive and transform uses the TF-IDF fallback
+    }
+    
+    // Keep the first target_dim left singular vectors as the LSA components
+    fn set_lsa_components(&mut self, (u_matrix, singular_values, tolerance): Decomposition, target_dim: usize) {
+        let nrows = u_matrix.nrows();
+        self.eigenvalue_spectrum = singular_values
+            .iter()
+            .map(|&sigma| if sigma.is_finite() { sigma * sigma } else { 0.0 })
+            .collect();
+        
+        // Select top k components from U matrix
+        // These represent the most important latent semantic dimensions
+        let mut components = DMatrix::zeros(target_dim, nrows);
+        
+        // Copy the first target_dim columns of U^T
+        // We transpose because we want each row to be a component
+        for i in 0..target_dim {
+            for j in 0..nrows {
+                components[(i, j)] = u_matrix[(j, i)];
             }
-            
-            // Optional: Weight components by singular values for better representation
-            // This gives more importance to stronger latent dimensions
-            let singular_values = svd.singular_values;
-            for i in 0..target_dim.min(singular_values.len()) {
-                let weight = singular_values[i].sqrt();
-                for j in 0..nrows {
-                    components[(i, j)] *= weight;
-                }
+        }
+        
+        // Singular vectors are only defined up to sign; flip each component so
+        // its largest loading is positive, making both solvers agree
+        for i in 0..target_dim {
+            let mut row = components.row_mut(i);
+            let largest = row.iter().copied().fold(0.0f32, |acc, x| if x.abs() > acc.abs() { x } else { acc });
+            if largest < 0.0 {
+                row.neg_mut();
             }
-            
-            self.lsa_components = Some(components);
-        } else {
-            // Fallback to identity-like transformation if SVD fails
-            let mut components = DMatrix::zeros(target_dim, nrows);
-            for i in 0..target_dim.min(nrows) {
-                components[(i, i)] = 1.0;
+        }
+        
+        // Optional: Weight components by singular values for better representation
+        // This gives more importance to stronger latent dimensions.
+        // Directions with a (numerically) zero singular value are arbitrary on
+        // degenerate corpora (e.g. an all-zero matrix), so they are zeroed out.
+        let mut kept_values = vec![0.0; target_dim];
+        for (i, kept) in kept_values.iter_mut().enumerate() {
+            let weight = match singular_values.get(i) {
+                Some(&sigma) if sigma.is_finite() && sigma > tolerance => {
+                    *kept = sigma;
+                    sigma.sqrt()
+                }
+                _ => 0.0,
+            };
+            for j in 0..nrows {
+                components[(i, j)] *= weight;
             }
-            self.lsa_components = Some(components);
         }
+        
+        // Last line of defence against NaN/inf leaking into embeddings
+        components.apply(|x| {
+            if !x.is_finite() {
+                *x = 0.0;
+            }
+        });
+        
+        self.lsa_components = Some(components);
+        self.singular_values = kept_values;
     }
     
     // Transform a document to embedding vector
@@ -138,42 +552,131 @@ impl TfIdfLsa {
         
         // Return zero vector if vocabulary is empty
         if vocab_size == 0 {
-            return vec![0.0; self.embedding_dim];
+            return vec![0.0; self.config.embedding_dim];
         }
         
         // Calculate TF-IDF vector for the document
-        let mut tfidf_vec = vec![0f32; vocab_size];
-        let mut tf_counts = vec![0f32; vocab_size];
-        
-        // Count term frequencies
-        for token in tokens {
-            if let Some(&idx) = self.vocabulary.get(token) {
-                tf_counts[idx] += 1.0;
-            }
-        }
-        
-        // Normalize and apply IDF
-        let total_terms = tokens.len() as f32;
-        if total_terms > 0.0 {
-            for (idx, &count) in tf_counts.iter().enumerate() {
-                if count > 0.0 && idx < self.idf_weights.len() {
-                    let tf = count / total_terms;
-                    tfidf_vec[idx] = tf * self.idf_weights[idx];
-                }
-            }
-        }
+        let mut tfidf_vec = self.tfidf_vector(tokens);
         
         // Apply LSA transformation if available
-        if let Some(ref components) = self.lsa_components {
+        let mut embedding = if let Some(ref components) = self.lsa_components {
             let tfidf_vector = DVector::from_vec(tfidf_vec);
-            let embedded = components * tfidf_vector;
-            embedded.iter().cloned().collect()
+            let mut embedded: Vec<f32> = (components * tfidf_vector).iter().cloned().collect();
+            self.scale_projection(&mut embedded);
+            embedded
         } else {
             // Return truncated TF-IDF vector if LSA not available
-            tfidf_vec.truncate(self.embedding_dim);
-            tfidf_vec.resize(self.embedding_dim, 0.0);
+            tfidf_vec.truncate(self.config.embedding_dim);
+            tfidf_vec.resize(self.config.embedding_dim, 0.0);
             tfidf_vec
+        };
+        
+        if self.config.normalize_output {
+            l2_normalize(&mut embedding);
+        }
+        embedding
+    }
+    
+    // Components already carry a √σ weight, so dividing by σ^(3/2) yields Σ⁻¹Uᵀq.
+    // Discarded directions (σ = 0) stay zero.
+    fn scale_projection(&self, embedded: &mut [f32]) {
+        if !self.config.scale_by_singular_values {
+            return;
         }
+        for (value, &sigma) in embedded.iter_mut().zip(&self.singular_values) {
+            *value = if sigma > 0.0 { *value / sigma.powf(1.5) } else { 0.0 };
+        }
+    }
+    
+    // For each LSA component, the top_n vocabulary terms with the largest absolute
+    // loadings (strongest first). Empty when LSA is inactive.
+    pub fn top_terms_per_component(&self, top_n: usize) -> Vec<Vec<(String, f32)>> {
+        let Some(ref components) = self.lsa_components else {
+            return Vec::new();
+        };
+        
+        // Reverse vocabulary lookup: column index -> token
+        let mut index_to_token = vec![None; components.ncols()];
+        for (token, &idx) in &self.vocabulary {
+            if idx < index_to_token.len() {
+                index_to_token[idx] = Some(token);
+            }
+        }
+        
+        components
+            .row_iter()
+            .map(|row| {
+                let mut loadings: Vec<(usize, f32)> = row.iter().cloned().enumerate().collect();
+                loadings.sort_by(|a, b| b.1.abs().partial_cmp(&a.1.abs()).unwrap_or(std::cmp::Ordering::Equal));
+                loadings
+                    .into_iter()
+                    .filter_map(|(idx, loading)| index_to_token[idx].map(|token| (token.clone(), loading)))
+                    .take(top_n)
+                    .collect()
+            })
+            .collect()
+    }
+    
+    // The n vocabulary terms whose LSA loadings (columns of the components) are
+    // most cosine-similar to token's, strongest first. Empty when LSA is inactive
+    // or the token is unknown.
+    pub fn similar_terms(&self, token: &str, n: usize) -> Vec<(String, f32)> {
+        let (Some(components), Some(&idx)) = (self.lsa_components.as_ref(), self.vocabulary.get(token)) else {
+            return Vec::new();
+        };
+        let target: Vec<f32> = components.column(idx).iter().cloned().collect();
+        if is_zero_vector(&target) {
+            return Vec::new();
+        }
+        
+        let mut similar: Vec<(String, f32)> = self.vocabulary
+            .iter()
+            .filter(|&(_, &other)| other != idx)
+            .map(|(term, &other)| {
+                let loadings: Vec<f32> = components.column(other).iter().cloned().collect();
+                (term.clone(), cosine_similarity(&target, &loadings))
+            })
+            .filter(|(_, similarity)| *similarity > 0.0)
+            .collect();
+        similar.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        similar.truncate(n);
+        similar
+    }
+    
+    // The LSA-space vector one occurrence of token adds to an embedding: its
+    // components column weighted by IDF (and dictionary boost). Without TF
+    // normalization, position decay or normalize_output, transform of a document
+    // is the sum of its tokens' contributions divided by its token count. None when
+    // LSA is inactive or the token is unknown.
+    pub fn token_contribution(&self, token: &str) -> Option<Vec<f32>> {
+        let components = self.lsa_components.as_ref()?;
+        let &idx = self.vocabulary.get(token)?;
+        
+        let mut weight = self.idf_weights.get(idx).copied().unwrap_or(0.0);
+        if self.boosted_terms.contains(token) {
+            weight *= self.config.dictionary_boost;
+        }
+        let mut contribution: Vec<f32> = components.column(idx).iter().map(|&loading| loading * weight).collect();
+        self.scale_projection(&mut contribution);
+        Some(contribution)
+    }
+    
+    pub fn set_binary_tf(&mut self, binary_tf: bool) {
+        self.config.binary_tf = binary_tf;
+    }
+    
+    pub fn is_binary_tf(&self) -> bool {
+        self.config.binary_tf
+    }
+    
+    // Terms whose TF-IDF weight is multiplied by the dictionary boost. Set before
+    // fit so the boost is reflected in the LSA components.
+    pub fn set_boosted_terms<I: IntoIterator<Item = String>>(&mut self, terms: I) {
+        self.boosted_terms = terms.into_iter().collect();
+    }
+    
+    pub fn set_dictionary_boost(&mut self, boost: f32) {
+        self.config.dictionary_boost = boost;
     }
     
     // Get vocabulary size
@@ -181,9 +684,76 @@ impl TfIdfLsa {
         self.vocabulary.len()
     }
     
+    // Whether transform projects into the LSA space (false means TF-IDF fallback)
+    pub fn is_lsa_active(&self) -> bool {
+        self.lsa_components.is_some()
+    }
+    
+    // Get the number of documents the model was fitted on (including folded-in ones)
+    pub fn documents_count(&self) -> usize {
+        self.documents_count
+    }
+    
+    // IDF weights indexed like the vocabulary (see feature_names)
+    pub fn idf_weights(&self) -> &[f32] {
+        &self.idf_weights
+    }
+    
+    // IDF weight of a vocabulary token
+    pub fn idf_for_token(&self, token: &str) -> Option<f32> {
+        self.vocabulary
+            .get(token)
+            .and_then(|&idx| self.idf_weights.get(idx))
+            .copied()
+    }
+    
+    // Get token to index mapping
+    pub fn vocabulary(&self) -> &HashMap<String, usize> {
+        &self.vocabulary
+    }
+    
+    // Tokens (added, removed) going from other's vocabulary to this one, each sorted
+    pub fn vocabulary_diff(&self, other: &TfIdfLsa) -> (Vec<String>, Vec<String>) {
+        let mut added: Vec<String> = self.vocabulary
+            .keys()
+            .filter(|token| !other.vocabulary.contains_key(*token))
+            .cloned()
+            .collect();
+        let mut removed: Vec<String> = other.vocabulary
+            .keys()
+            .filter(|token| !self.vocabulary.contains_key(*token))
+            .cloned()
+            .collect();
+        added.sort();
+        removed.sort();
+        (added, removed)
+    }
+    
     // Get embedding dimension
     pub fn embedding_dim(&self) -> usize {
-        self.embedding_dim
+        self.config.embedding_dim
+    }
+    
+    // Singular values of the fitted LSA components, strongest first
+    pub fn singular_values(&self) -> &[f32] {
+        &self.singular_values
+    }
+    
+    // Full eigenvalue spectrum (σ², descending) of the last LSA decomposition, not
+    // only the embedding_dim retained values: min(documents, terms) values after a
+    // fit. After update_with_documents only the iterated ones (embedding_dim + 2)
+    // are known. Its decay shows how much each further dimension would add. Empty
+    // when LSA is inactive.
+    pub fn eigenvalue_spectrum(&self) -> &[f32] {
+        &self.eigenvalue_spectrum
+    }
+    
+    // Length of the vectors transform returns. Equal to embedding_dim unless the
+    // LSA was fitted on fewer documents or terms than embedding_dim.
+    pub fn output_dim(&self) -> usize {
+        self.lsa_components
+            .as_ref()
+            .map_or(self.config.embedding_dim, |components| components.nrows())
     }
     
     // Export model to JSON
@@ -193,19 +763,142 @@ impl TfIdfLsa {
     
     // Import model from JSON
     pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        let version = read_format_version(json)?;
+        if version != FORMAT_VERSION {
+            return Err(serde::de::Error::custom(format!(
+                "unsupported format version {} (expected {})",
+                version, FORMAT_VERSION
+            )));
+        }
         serde_json::from_str(json)
     }
 }
 
+// Left singular vectors (as columns), singular values (descending) and the
+// tolerance below which a singular value counts as zero
+type Decomposition = (DMatrix<f32>, DVector<f32>, f32);
+
+// Perform Singular Value Decomposition (SVD)
+// TF-IDF matrix = U * Σ * V^T
+// Where U contains left singular vectors (document-concept relationships)
+// Σ contains singular values (importance of each concept)
+// V^T contains right singular vectors (term-concept relationships)
+fn svd_decomposition(matrix: DMatrix<f32>) -> Option<Decomposition> {
+    let (nrows, ncols) = matrix.shape();
+    let svd = SVD::new(matrix, true, false);
+    let tolerance = svd.singular_values.max() * f32::EPSILON * nrows.max(ncols) as f32;
+    svd.u.map(|u| (u, svd.singular_values, tolerance))
+}
+
+// Size of the matrix the SVD or Gram solver factorizes for a terms x documents matrix
+fn decomposed_elements(gram: bool, nrows: usize, ncols: usize) -> usize {
+    if gram {
+        ncols * ncols
+    } else {
+        nrows * ncols
+    }
+}
+
+// Same decomposition via the documents x documents Gram matrix: XᵀX = V Σ² Vᵀ, and
+// U = X V Σ⁻¹. Squaring loses precision, so singular values below
+// σ_max * sqrt(ε * n) are treated as zero (the SVD path keeps down to σ_max * ε * n).
+fn gram_decomposition(matrix: &DMatrix<f32>) -> Option<Decomposition> {
+    let (nrows, ncols) = matrix.shape();
+    let eigen = (matrix.transpose() * matrix).symmetric_eigen();
+    
+    let mut order: Vec<usize> = (0..ncols).collect();
+    order.sort_by(|&a, &b| eigen.eigenvalues[b].total_cmp(&eigen.eigenvalues[a]));
+    let singular_values = DVector::from_iterator(
+        ncols,
+        order.iter().map(|&i| eigen.eigenvalues[i].max(0.0).sqrt()),
+    );
+    let tolerance = singular_values.max() * (f32::EPSILON * nrows.max(ncols) as f32).sqrt();
+    
+    let mut u = DMatrix::zeros(nrows, ncols);
+    for (k, &i) in order.iter().enumerate() {
+        let sigma = singular_values[k];
+        if sigma > tolerance {
+            u.set_column(k, &(matrix * eigen.eigenvectors.column(i) / sigma));
+        }
+    }
+    Some((u, singular_values, tolerance))
+}
+
+// Upper bound on the block power iterations of covariance_decomposition
+const MAX_POWER_ITERATIONS: usize = 200;
+
+// Top k eigenpairs of the terms x terms covariance XXᵀ = U Σ² Uᵀ by block power
+// iteration with Rayleigh-Ritz extraction. Each step costs one covariance times
+// (k + 2)-column product rather than a full decomposition, and starting from the
+// current components (rows of warm_start) it converges in a few steps when new
+// documents shift the latent space only slightly. Squaring loses precision as in
+// gram_decomposition, hence the same looser tolerance.
+fn covariance_decomposition(covariance: &DMatrix<f32>, k: usize, warm_start: Option<&DMatrix<f32>>) -> Option<Decomposition> {
+    let n = covariance.nrows();
+    if n == 0 || k == 0 {
+        return None;
+    }
+    // Two extra vectors speed up convergence of the k-th eigenvector
+    let block = (k + 2).min(n);
+    
+    let mut basis = DMatrix::zeros(n, block);
+    if let Some(components) = warm_start.filter(|components| components.ncols() == n) {
+        for i in 0..components.nrows().min(block) {
+            basis.set_column(i, &components.row(i).transpose());
+        }
+    }
+    // Columns without a usable start (discarded directions, new model) start random
+    let mut rng = SeededRng::new(42);
+    for mut column in basis.column_iter_mut() {
+        if column.norm() <= f32::EPSILON {
+            column.iter_mut().for_each(|x| *x = rng.next_f32() - 0.5);
+        }
+    }
+    let mut basis = basis.qr().q();
+    
+    let mut ritz_values = vec![0.0f32; block];
+    let mut ritz = None;
+    for _ in 0..MAX_POWER_ITERATIONS {
+        let product = covariance * &basis;
+        let eigen = (basis.transpose() * &product).symmetric_eigen();
+        let mut values: Vec<f32> = eigen.eigenvalues.iter().copied().collect();
+        values.sort_by(|a, b| b.total_cmp(a));
+        
+        let scale = values.first().copied().unwrap_or(0.0).abs().max(f32::MIN_POSITIVE);
+        let converged = values.iter().zip(&ritz_values).all(|(a, b)| (a - b).abs() <= 1e-6 * scale);
+        ritz_values = values;
+        ritz = Some((basis.clone(), eigen));
+        if converged {
+            break;
+        }
+        basis = product.qr().q();
+    }
+    let (basis, eigen) = ritz?;
+    
+    // Rotate the basis onto the Ritz vectors, strongest first
+    let mut order: Vec<usize> = (0..block).collect();
+    order.sort_by(|&a, &b| eigen.eigenvalues[b].total_cmp(&eigen.eigenvalues[a]));
+    let rotated = &basis * &eigen.eigenvectors;
+    let mut u = DMatrix::zeros(n, block);
+    for (k, &i) in order.iter().enumerate() {
+        u.set_column(k, &rotated.column(i));
+    }
+    let singular_values = DVector::from_iterator(
+        block,
+        order.iter().map(|&i| eigen.eigenvalues[i].max(0.0).sqrt()),
+    );
+    let tolerance = singular_values.max() * (f32::EPSILON * n as f32).sqrt();
+    Some((u, singular_values, tolerance))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::tokenizer::JapaneseTokenizer;
     
     #[test]
     fn test_tfidf_lsa_basic() {
         let tokenizer = JapaneseTokenizer::new();
-        let documents = vec![
+        let documents = [
             "今日は天気がいいですね",
             "明日は雨が降りそうです",
             "今日は映画を見ました",
@@ -237,6 +930,693 @@ mod tests {
         assert!(sum > 0.0);
     }
     
+    #[test]
+    fn test_tiny_vocabulary_disables_lsa() {
+        let vocab = HashMap::from([
+            ("今日".to_string(), 0),
+            ("明日".to_string(), 1),
+            ("天気".to_string(), 2),
+        ]);
+        let documents = vec![
+            vec!["今日".to_string(), "天気".to_string()],
+            vec!["明日".to_string(), "天気".to_string()],
+        ];
+        
+        let mut model = TfIdfLsa::new(8);
+        model.fit(&documents, vocab);
+        assert!(!model.is_lsa_active());
+        
+        // Embeddings keep the configured dimension and remain comparable
+        let vec1 = model.transform(&["今日".to_string(), "天気".to_string()]);
+        let vec2 = model.transform(&["今日".to_string()]);
+        let vec3 = model.transform(&["明日".to_string()]);
+        assert_eq!(vec1.len(), 8);
+        assert!(crate::utils::cosine_similarity(&vec1, &vec2) > 0.0);
+        assert_eq!(crate::utils::cosine_similarity(&vec2, &vec3), 0.0);
+    }
+    
+    #[test]
+    fn test_fold_in() {
+        let tokenizer = JapaneseTokenizer::new();
+        let documents = [
+            "今日は天気がいいですね",
+            "明日は雨が降りそうです",
+            "今日は映画を見ました",
+        ];
+        let tokenized_docs: Vec<Vec<String>> = documents
+            .iter()
+            .map(|doc| tokenizer.tokenize(doc))
+            .collect();
+        let vocab = tokenizer.build_vocabulary(&documents.iter().map(|s| s.to_string()).collect::<Vec<_>>());
+        
+        let mut model = TfIdfLsa::new(2);
+        model.fit(&tokenized_docs, vocab);
+        assert!(model.is_lsa_active());
+        let vocab_size = model.vocab_size();
+        let idf_before = model.idf_weights[model.vocabulary["今日"]];
+        
+        model.fold_in(&["今日".to_string(), "新語".to_string()]);
+        
+        assert_eq!(model.documents_count(), 4);
+        assert_eq!(model.vocab_size(), vocab_size + 1);
+        assert!(model.idf_weights[model.vocabulary["新語"]] > 0.0);
+        assert!(model.idf_weights[model.vocabulary["今日"]] < idf_before + 1e-6);
+        
+        // LSA components stay fixed but must still cover the grown vocabulary
+        let embedding = model.transform(&["新語".to_string(), "今日".to_string()]);
+        assert_eq!(embedding.len(), 2);
+    }
+    
+    #[test]
+    fn test_top_terms_per_component() {
+        let tokenizer = JapaneseTokenizer::new();
+        let weather = ["今日の天気は晴れです", "明日の天気は晴れです", "週末の天気は晴れです"];
+        let programming = ["プログラミング言語を学ぶ", "新しいプログラミング言語", "プログラミング言語の設計"];
+        let documents: Vec<String> = weather.iter().chain(programming.iter()).map(|s| s.to_string()).collect();
+        let tokenized_docs: Vec<Vec<String>> = documents.iter().map(|doc| tokenizer.tokenize(doc)).collect();
+        let vocab = tokenizer.build_vocabulary(&documents);
+        
+        let mut model = TfIdfLsa::new(2);
+        model.fit(&tokenized_docs, vocab);
+        
+        let top_terms = model.top_terms_per_component(3);
+        assert_eq!(top_terms.len(), 2);
+        
+        let weather_tokens: HashSet<String> = tokenized_docs[..3].iter().flatten().cloned().collect();
+        let programming_tokens: HashSet<String> = tokenized_docs[3..].iter().flatten().cloned().collect();
+        let coherent = top_terms.iter().any(|terms| {
+            terms.len() == 3
+                && (terms.iter().all(|(t, _)| weather_tokens.contains(t) && !programming_tokens.contains(t))
+                    || terms.iter().all(|(t, _)| programming_tokens.contains(t) && !weather_tokens.contains(t)))
+        });
+        assert!(coherent, "expected a topic-coherent component: {:?}", top_terms);
+    }
+    
+    #[test]
+    fn test_binary_tf() {
+        let vocab = HashMap::from([
+            ("今日".to_string(), 0),
+            ("明日".to_string(), 1),
+            ("天気".to_string(), 2),
+        ]);
+        let documents = vec![
+            vec!["今日".to_string(), "天気".to_string()],
+            vec!["明日".to_string(), "天気".to_string()],
+        ];
+        
+        let mut model = TfIdfLsa::new(8);
+        model.set_binary_tf(true);
+        model.fit(&documents, vocab);
+        
+        let once = model.transform(&["今日".to_string(), "明日".to_string()]);
+        let repeated = model.transform(&["今日".to_string(), "今日".to_string(), "今日".to_string(), "明日".to_string()]);
+        assert_eq!(once, repeated);
+        
+        // The choice survives serialization
+        let restored = TfIdfLsa::from_json(&model.to_json().unwrap()).unwrap();
+        assert!(restored.is_binary_tf());
+    }
+    
+    #[test]
+    fn test_dictionary_boost() {
+        let tokens = |words: &[&str]| words.iter().map(|w| w.to_string()).collect::<Vec<_>>();
+        let documents = vec![
+            tokens(&["人工知能", "研究"]),
+            tokens(&["人工知能", "応用"]),
+            tokens(&["天気", "予報"]),
+            tokens(&["天気", "観測"]),
+        ];
+        let vocab: HashMap<String, usize> = ["人工知能", "研究", "応用", "天気", "予報", "観測"]
+            .iter()
+            .enumerate()
+            .map(|(idx, token)| (token.to_string(), idx))
+            .collect();
+        
+        // Dimension above the vocabulary size keeps the raw TF-IDF weights
+        let mut model = TfIdfLsa::new(8);
+        model.set_dictionary_boost(3.0);
+        model.set_boosted_terms(vec!["人工知能".to_string()]);
+        model.fit(&documents, vocab);
+        
+        let vectors: Vec<Vec<f32>> = documents.iter().map(|doc| model.transform(doc)).collect();
+        let dictionary_pair = crate::utils::cosine_similarity(&vectors[0], &vectors[1]);
+        let plain_pair = crate::utils::cosine_similarity(&vectors[2], &vectors[3]);
+        assert!(dictionary_pair > plain_pair);
+    }
+    
+    #[test]
+    fn test_fit_transform_matches_transform() {
+        let tokenizer = JapaneseTokenizer::new();
+        let documents: Vec<String> = [
+            "今日は天気がいいですね",
+            "明日は雨が降りそうです",
+            "今日は映画を見ました",
+            "天気予報では明日も晴れです",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        let tokenized: Vec<Vec<String>> = documents.iter().map(|doc| tokenizer.tokenize(doc)).collect();
+        let vocab = tokenizer.build_vocabulary(&documents);
+        
+        for dim in [2, 1000] {
+            let mut model = TfIdfLsa::new(dim);
+            let embeddings = model.fit_transform(&tokenized, vocab.clone());
+            assert_eq!(embeddings.len(), tokenized.len());
+            
+            for (embedding, tokens) in embeddings.iter().zip(&tokenized) {
+                let mut expected = model.transform(tokens);
+                l2_normalize(&mut expected);
+                assert_eq!(embedding.len(), expected.len());
+                for (a, b) in embedding.iter().zip(&expected) {
+                    assert!((a - b).abs() < 1e-5);
+                }
+            }
+        }
+    }
+    
+    #[test]
+    fn test_degenerate_corpus_has_no_nan() {
+        // One token in every document: IDF is 0, so the TF-IDF matrix is all zeros
+        let documents = vec![vec!["天気".to_string()]; 3];
+        let vocab = HashMap::from([("天気".to_string(), 0)]);
+        
+        let mut model = TfIdfLsa::new(1);
+        model.fit(&documents, vocab);
+        assert!(model.is_lsa_active());
+        assert!(model.lsa_components.as_ref().unwrap().iter().all(|x| x.is_finite()));
+        
+        for tokens in [vec!["天気".to_string()], vec![], vec!["映画".to_string()]] {
+            let embedding = model.transform(&tokens);
+            assert!(embedding.iter().all(|x| x.is_finite()));
+        }
+    }
+    
+    #[test]
+    fn test_idf_accessors() {
+        let documents = vec![
+            vec!["天気".to_string(), "映画".to_string()],
+            vec!["天気".to_string()],
+            vec!["天気".to_string(), "雨".to_string()],
+        ];
+        let vocab = HashMap::from([
+            ("天気".to_string(), 0),
+            ("映画".to_string(), 1),
+            ("雨".to_string(), 2),
+        ]);
+        
+        let mut model = TfIdfLsa::new(8);
+        model.fit(&documents, vocab);
+        
+        assert_eq!(model.idf_weights().len(), 3);
+        assert_eq!(model.idf_for_token("映画"), Some(model.idf_weights()[1]));
+        assert!(model.idf_for_token("映画").unwrap() > model.idf_for_token("天気").unwrap());
+        assert_eq!(model.idf_for_token("東京"), None);
+    }
+    
+    #[test]
+    fn test_exclude_tokens() {
+        let documents = vec![
+            vec!["天気".to_string(), "ノイズ".to_string()],
+            vec!["映画".to_string()],
+            vec!["雨".to_string(), "ノイズ".to_string()],
+        ];
+        let vocab = HashMap::from([
+            ("天気".to_string(), 0),
+            ("映画".to_string(), 1),
+            ("雨".to_string(), 2),
+            ("ノイズ".to_string(), 3),
+        ]);
+        let mut model = TfIdfLsa::new(2);
+        model.fit(&documents, vocab);
+        
+        let noisy = vec!["ノイズ".to_string()];
+        assert!(!crate::utils::is_zero_vector(&model.transform(&noisy)));
+        
+        model.exclude_tokens(&noisy);
+        assert_eq!(model.idf_for_token("ノイズ"), Some(0.0));
+        assert!(crate::utils::is_zero_vector(&model.transform(&noisy)));
+        
+        // Still excluded after folding in another document
+        model.fold_in(&["ノイズ".to_string(), "映画".to_string()]);
+        assert!(crate::utils::is_zero_vector(&model.transform(&noisy)));
+    }
+    
+    #[test]
+    fn test_scale_by_singular_values() {
+        let tokenizer = JapaneseTokenizer::new();
+        let documents: Vec<String> = [
+            "今日は天気がいいですね",
+            "明日は雨が降りそうです",
+            "今日は映画を見ました",
+            "天気は晴れです",
+            "映画は面白かったです",
+        ].iter().map(|s| s.to_string()).collect();
+        let tokenized_docs: Vec<Vec<String>> = documents.iter().map(|doc| tokenizer.tokenize(doc)).collect();
+        
+        let rank = |scale: bool| {
+            let mut model = TfIdfLsa::with_config(TfIdfLsaConfig {
+                embedding_dim: 4,
+                scale_by_singular_values: scale,
+                ..TfIdfLsaConfig::default()
+            });
+            let embeddings = model.fit_transform(&tokenized_docs, tokenizer.build_vocabulary(&documents));
+            assert!(model.singular_values().windows(2).all(|pair| pair[0] >= pair[1]));
+            
+            let mut query = model.transform(&tokenizer.tokenize("天気は晴れです"));
+            l2_normalize(&mut query);
+            let mut ranking: Vec<(usize, f32)> = embeddings
+                .iter()
+                .map(|doc| doc.iter().zip(&query).map(|(a, b)| a * b).sum())
+                .enumerate()
+                .collect();
+            ranking.sort_by(|a, b| b.1.total_cmp(&a.1));
+            (embeddings, ranking)
+        };
+        
+        let (unscaled, unscaled_ranking) = rank(false);
+        let (scaled, scaled_ranking) = rank(true);
+        assert_ne!(unscaled, scaled);
+        assert_eq!(unscaled_ranking[0].0, 3);
+        assert_eq!(scaled_ranking[0].0, 3);
+        assert!((scaled_ranking[0].1 - 1.0).abs() < 1e-4);
+    }
+    
+    #[test]
+    fn test_fit_raw() {
+        let tokenizer = JapaneseTokenizer::new();
+        let documents: Vec<String> = [
+            "今日は天気がいいですね",
+            "明日は雨が降りそうです",
+            "今日は映画を見ました",
+        ].iter().map(|s| s.to_string()).collect();
+        
+        let mut model = TfIdfLsa::new(2);
+        model.fit_raw(&documents, &tokenizer);
+        assert!(model.is_lsa_active());
+        assert_eq!(model.documents_count(), 3);
+        
+        let embedding = model.transform(&tokenizer.tokenize("今日は晴れです"));
+        assert_eq!(embedding.len(), 2);
+        assert!(embedding.iter().any(|&x| x != 0.0));
+        
+        let tokenized_docs: Vec<Vec<String>> = documents.iter().map(|doc| tokenizer.tokenize(doc)).collect();
+        let mut manual = TfIdfLsa::new(2);
+        manual.fit(&tokenized_docs, tokenizer.build_vocabulary(&documents));
+        assert_eq!(manual.transform(&tokenizer.tokenize("今日は晴れです")), embedding);
+    }
+    
+    #[test]
+    fn test_position_decay() {
+        let tokens = |words: &[&str]| -> Vec<String> { words.iter().map(|w| w.to_string()).collect() };
+        let mut model = TfIdfLsa::with_config(TfIdfLsaConfig {
+            embedding_dim: 8,
+            position_decay: 0.5,
+            ..TfIdfLsaConfig::default()
+        });
+        model.fit(
+            &[tokens(&["天気", "映画"]), tokens(&["料理"])],
+            HashMap::from([("天気".to_string(), 0), ("映画".to_string(), 1), ("料理".to_string(), 2)]),
+        );
+        
+        let early = model.transform(&tokens(&["天気", "映画", "料理"]));
+        let late = model.transform(&tokens(&["映画", "料理", "天気"]));
+        assert!(early[0] > late[0]);
+        
+        let tokenizer = JapaneseTokenizer::new();
+        let ordered = tokenizer.tokenize_ordered("映画と天気");
+        let position = |token: &str| ordered.iter().position(|t| t == token).unwrap();
+        assert!(position("映画") < position("天気"));
+        let mut sorted = ordered.clone();
+        sorted.sort();
+        assert_eq!(sorted, tokenizer.tokenize("映画と天気"));
+        
+        // fit_raw decays by position in the text, not by alphabetical rank
+        let documents: Vec<String> = ["映画と天気の話", "料理と天気の話", "映画と料理の話"]
+            .iter()
+            .map(|doc| doc.to_string())
+            .collect();
+        let fit_with = |tokenize: &dyn Fn(&str) -> Vec<String>| {
+            let mut model = TfIdfLsa::with_config(TfIdfLsaConfig {
+                embedding_dim: 2,
+                position_decay: 0.5,
+                ..TfIdfLsaConfig::default()
+            });
+            let tokenized: Vec<Vec<String>> = documents.iter().map(|doc| tokenize(doc)).collect();
+            model.fit(&tokenized, tokenizer.build_vocabulary_from_tokens(&tokenized));
+            model
+        };
+        let mut raw = TfIdfLsa::with_config(TfIdfLsaConfig {
+            embedding_dim: 2,
+            position_decay: 0.5,
+            ..TfIdfLsaConfig::default()
+        });
+        raw.fit_raw(&documents, &tokenizer);
+        assert!(raw.is_lsa_active());
+        assert_eq!(raw.lsa_components, fit_with(&|doc| tokenizer.tokenize_ordered(doc)).lsa_components);
+        assert_ne!(raw.lsa_components, fit_with(&|doc| tokenizer.tokenize(doc)).lsa_components);
+    }
+    
+    #[test]
+    fn test_vocabulary_diff() {
+        let tokenizer = JapaneseTokenizer::new();
+        let fit = |documents: &[&str]| {
+            let documents: Vec<String> = documents.iter().map(|s| s.to_string()).collect();
+            let mut model = TfIdfLsa::new(4);
+            model.fit_raw(&documents, &tokenizer);
+            model
+        };
+        let old = fit(&["今日は天気がいいですね", "明日は雨が降りそうです"]);
+        let new = fit(&["今日は天気がいいですね", "週末は映画を見ました"]);
+        
+        let (added, removed) = new.vocabulary_diff(&old);
+        assert!(added.contains(&"映画".to_string()));
+        assert!(removed.contains(&"降り".to_string()));
+        assert!(!added.contains(&"天気".to_string()));
+        assert!(!removed.contains(&"天気".to_string()));
+        assert_eq!(old.vocabulary_diff(&new), (removed, added));
+        assert_eq!(new.vocabulary_diff(&new), (Vec::new(), Vec::new()));
+    }
+    
+    #[test]
+    fn test_transform_matrix_matches_transform() {
+        let tokenizer = JapaneseTokenizer::new();
+        let documents: Vec<String> = [
+            "今日は天気がいいですね",
+            "明日は雨が降りそうです",
+            "今日は映画を見ました",
+        ].iter().map(|s| s.to_string()).collect();
+        let queries: Vec<Vec<String>> = ["今日は晴れです", "映画館", "", "明日の天気"]
+            .iter()
+            .map(|query| tokenizer.tokenize(query))
+            .collect();
+        
+        for dim in [2, 1000] {
+            let mut model = TfIdfLsa::new(dim);
+            model.fit_raw(&documents, &tokenizer);
+            
+            let batch = model.transform_matrix(&queries);
+            assert_eq!(batch.len(), queries.len());
+            for (embedding, tokens) in batch.iter().zip(&queries) {
+                let mut expected = model.transform(tokens);
+                l2_normalize(&mut expected);
+                assert_eq!(embedding.len(), expected.len());
+                for (a, b) in embedding.iter().zip(&expected) {
+                    assert!((a - b).abs() < 1e-5);
+                }
+            }
+        }
+    }
+    
+    #[test]
+    fn test_normalize_output() {
+        let tokenizer = JapaneseTokenizer::new();
+        let documents: Vec<String> = [
+            "今日は天気がいいですね",
+            "明日は雨が降りそうです",
+            "今日は映画を見ました",
+        ].iter().map(|s| s.to_string()).collect();
+        let query = tokenizer.tokenize("今日は晴れです");
+        let norm = |vec: &[f32]| vec.iter().map(|x| x * x).sum::<f32>().sqrt();
+        
+        for dim in [2, 1000] {
+            let mut model = TfIdfLsa::new(dim);
+            model.fit_raw(&documents, &tokenizer);
+            let raw = model.transform(&query);
+            assert!((norm(&raw) - 1.0).abs() > 1e-3);
+            
+            let mut model = TfIdfLsa::with_config(TfIdfLsaConfig {
+                embedding_dim: dim,
+                normalize_output: true,
+                ..TfIdfLsaConfig::default()
+            });
+            model.fit_raw(&documents, &tokenizer);
+            let normalized = model.transform(&query);
+            assert!((norm(&normalized) - 1.0).abs() < 1e-5);
+            for (a, b) in normalized.iter().zip(&raw) {
+                assert!((a - b / norm(&raw)).abs() < 1e-5);
+            }
+        }
+    }
+    
+    #[test]
+    fn test_gram_solver_matches_svd() {
+        // Many terms, few documents
+        let documents: Vec<Vec<String>> = (0..6)
+            .map(|doc| (0..40).map(|term| format!("t{}", (doc * 7 + term * term) % 150)).collect())
+            .collect();
+        let vocabulary: HashMap<String, usize> = (0..150).map(|term| (format!("t{}", term), term)).collect();
+        
+        let fit = |solver: LsaSolver| {
+            let mut model = TfIdfLsa::with_config(TfIdfLsaConfig {
+                embedding_dim: 4,
+                lsa_solver: solver,
+                ..TfIdfLsaConfig::default()
+            });
+            let embeddings = model.fit_transform(&documents, vocabulary.clone());
+            (model, embeddings)
+        };
+        let (svd_model, svd_embeddings) = fit(LsaSolver::Svd);
+        let (gram_model, gram_embeddings) = fit(LsaSolver::Gram);
+        
+        for (a, b) in svd_model.singular_values().iter().zip(gram_model.singular_values()) {
+            assert!((a - b).abs() < 1e-3 * a.max(1.0), "{} vs {}", a, b);
+        }
+        for (a, b) in svd_embeddings.iter().zip(&gram_embeddings) {
+            for (x, y) in a.iter().zip(b) {
+                assert!((x - y).abs() < 1e-3, "{} vs {}", x, y);
+            }
+        }
+        
+        // Auto by default, picking Gram only with fewer documents than terms
+        let auto_model = TfIdfLsa::new(4);
+        assert_eq!(auto_model.config().lsa_solver, LsaSolver::Auto);
+        assert!(!svd_model.uses_gram_solver(vocabulary.len(), documents.len()));
+        assert!(auto_model.uses_gram_solver(vocabulary.len(), documents.len()));
+        assert!(!auto_model.uses_gram_solver(documents.len(), vocabulary.len()));
+        
+        // The Gram path factorizes a 6 x 6 matrix instead of the 150 x 6 one
+        let svd_size = decomposed_elements(false, vocabulary.len(), documents.len());
+        let gram_size = decomposed_elements(true, vocabulary.len(), documents.len());
+        assert_eq!((svd_size, gram_size), (900, 36));
+    }
+    
+    #[test]
+    fn test_update_with_documents_matches_full_covariance() {
+        let tokenizer = JapaneseTokenizer::new();
+        let initial: Vec<Vec<String>> = ["今日は天気がいいですね", "明日は雨が降りそうです", "今日は映画を見ました"]
+            .iter()
+            .map(|doc| tokenizer.tokenize(doc))
+            .collect();
+        let appended: Vec<Vec<String>> = ["明日は映画館に行きます", "雨の日は映画を見ます"]
+            .iter()
+            .map(|doc| tokenizer.tokenize(doc))
+            .collect();
+        let all: Vec<Vec<String>> = initial.iter().chain(&appended).cloned().collect();
+        let vocab = tokenizer.build_vocabulary_from_tokens(&all);
+        
+        let mut model = TfIdfLsa::with_config(TfIdfLsaConfig {
+            embedding_dim: 4,
+            cache_covariance: true,
+            ..TfIdfLsaConfig::default()
+        });
+        model.fit(&initial, vocab);
+        let idf_weights = model.idf_weights().to_vec();
+        let doc_freq = model.doc_freq.clone();
+        assert!(model.update_with_documents(&appended));
+        assert_eq!(model.output_dim(), 4);
+        
+        // Corpus statistics stay consistent with the IDF until the next full fit
+        assert_eq!(model.documents_count, 3);
+        assert_eq!(model.doc_freq, doc_freq);
+        assert_eq!(model.idf_weights(), idf_weights.as_slice());
+        
+        // IDF weights are unchanged by the update, so the full recomputation uses them too
+        let mut tfidf_matrix = DMatrix::zeros(model.feature_count(), all.len());
+        for (doc_idx, tokens) in all.iter().enumerate() {
+            tfidf_matrix.set_column(doc_idx, &DVector::from_vec(model.tfidf_vector(tokens)));
+        }
+        let expected = &tfidf_matrix * tfidf_matrix.transpose();
+        let cached = model.covariance().unwrap();
+        assert!((cached - &expected).abs().max() < 1e-4);
+        
+        // Components match a fresh decomposition of the full matrix
+        let svd = SVD::new(tfidf_matrix, true, false);
+        let u = svd.u.unwrap();
+        let top_sigma = svd.singular_values[0];
+        for (i, &sigma) in model.singular_values().iter().enumerate() {
+            assert!((sigma - svd.singular_values[i]).abs() < 1e-3 * top_sigma, "{} vs {}", sigma, svd.singular_values[i]);
+        }
+        let components = model.lsa_components.as_ref().unwrap();
+        let top_loadings = components.row(0).transpose().normalize();
+        assert!(top_loadings.dot(&u.column(0)).abs() > 0.999);
+        
+        // Refreshing the IDF invalidates the cache
+        model.fold_in(&appended[0]);
+        assert!(model.covariance().is_none());
+        assert!(!model.update_with_documents(&appended));
+    }
+    
+    #[test]
+    fn test_idf_floor() {
+        let documents: Vec<Vec<String>> = [["今日", "天気"], ["今日", "映画"], ["今日", "雨"]]
+            .iter()
+            .map(|doc| doc.iter().map(|token| token.to_string()).collect())
+            .collect();
+        let vocab: HashMap<String, usize> = ["今日", "天気", "映画", "雨"]
+            .iter()
+            .enumerate()
+            .map(|(idx, token)| (token.to_string(), idx))
+            .collect();
+        
+        let mut model = TfIdfLsa::new(8);
+        model.fit(&documents, vocab.clone());
+        assert_eq!(model.idf_for_token("今日"), Some(0.0));
+        
+        let mut floored = TfIdfLsa::with_config(TfIdfLsaConfig {
+            embedding_dim: 8,
+            idf_floor: 0.2,
+            ..TfIdfLsaConfig::default()
+        });
+        floored.fit(&documents, vocab);
+        assert_eq!(floored.idf_for_token("今日"), Some(0.2));
+        // Rarer terms are above the floor and unaffected
+        assert_eq!(floored.idf_for_token("天気"), model.idf_for_token("天気"));
+        assert!(floored.transform(&["今日".to_string()]).iter().any(|&x| x != 0.0));
+    }
+    
+    #[test]
+    fn test_token_contributions_sum_to_transform() {
+        let tokenizer = JapaneseTokenizer::new();
+        let documents: Vec<String> = ["今日は天気がいいですね", "明日は雨が降りそうです", "今日は映画を見ました"]
+            .iter()
+            .map(|doc| doc.to_string())
+            .collect();
+        let mut model = TfIdfLsa::new(2);
+        model.fit_raw(&documents, &tokenizer);
+        assert!(model.token_contribution("存在しない").is_none());
+        
+        let tokens = vec!["今日".to_string(), "天気".to_string()];
+        let mut summed = vec![0.0; model.output_dim()];
+        for token in &tokens {
+            for (sum, value) in summed.iter_mut().zip(model.token_contribution(token).unwrap()) {
+                *sum += value / tokens.len() as f32;
+            }
+        }
+        
+        let embedding = model.transform(&tokens);
+        assert!(embedding.iter().any(|&x| x != 0.0));
+        for (a, b) in summed.iter().zip(&embedding) {
+            assert!((a - b).abs() < 1e-5);
+        }
+    }
+    
+    #[test]
+    fn test_min_docs_for_lsa() {
+        let tokenizer = JapaneseTokenizer::new();
+        let documents: Vec<String> = ["今日は天気がいいですね", "明日は雨が降りそうです", "今日は映画を見ました"]
+            .iter()
+            .map(|doc| doc.to_string())
+            .collect();
+        
+        let mut model = TfIdfLsa::with_config(TfIdfLsaConfig {
+            embedding_dim: 2,
+            min_docs_for_lsa: 5,
+            ..TfIdfLsaConfig::default()
+        });
+        model.fit_raw(&documents, &tokenizer);
+        assert!(!model.is_lsa_active());
+        let embedding = model.transform(&tokenizer.tokenize("今日は天気"));
+        assert_eq!(embedding.len(), 2);
+        
+        let mut default_model = TfIdfLsa::new(2);
+        default_model.fit_raw(&documents, &tokenizer);
+        assert!(default_model.is_lsa_active());
+    }
+    
+    #[test]
+    fn test_eigenvalue_spectrum() {
+        let tokenizer = JapaneseTokenizer::new();
+        let documents: Vec<String> = [
+            "寿司と刺身を食べた",
+            "新鮮な寿司が美味しい",
+            "刺身の盛り合わせを注文した",
+            "野球の試合で投手が活躍した",
+            "野球場で試合を観戦した",
+        ]
+        .iter()
+        .map(|doc| doc.to_string())
+        .collect();
+        let mut model = TfIdfLsa::new(2);
+        model.fit_raw(&documents, &tokenizer);
+        
+        let spectrum = model.eigenvalue_spectrum();
+        assert_eq!(spectrum.len(), documents.len());
+        assert!(spectrum.windows(2).all(|pair| pair[0] >= pair[1]));
+        assert!(spectrum.iter().all(|&value| value >= 0.0));
+        assert!((spectrum[0] - model.singular_values()[0].powi(2)).abs() < 1e-6);
+    }
+    
+    #[test]
+    fn test_fit_is_deterministic() {
+        let tokenizer = JapaneseTokenizer::new();
+        let documents: Vec<String> = [
+            "今日は天気がいいですね",
+            "明日は雨が降りそうです",
+            "今日は映画を見ました",
+            "天気は晴れです",
+            "映画は面白かったです",
+        ].iter().map(|s| s.to_string()).collect();
+        
+        let fit_model = || {
+            let tokenized_docs: Vec<Vec<String>> = documents.iter().map(|doc| tokenizer.tokenize(doc)).collect();
+            let mut model = TfIdfLsa::new(4);
+            model.fit(&tokenized_docs, tokenizer.build_vocabulary(&documents));
+            model
+        };
+        
+        let model1 = fit_model();
+        let model2 = fit_model();
+        assert_eq!(model1.vocabulary, model2.vocabulary);
+        assert_eq!(model1.idf_weights, model2.idf_weights);
+        assert!(model1.is_lsa_active());
+        assert_eq!(model1.lsa_components, model2.lsa_components);
+    }
+    
+    #[test]
+    fn test_config_persists_through_serialization() {
+        let config = TfIdfLsaConfig {
+            embedding_dim: 1,
+            binary_tf: true,
+            dictionary_boost: 2.0,
+            scale_by_singular_values: true,
+            position_decay: 0.5,
+            normalize_output: true,
+            lsa_solver: LsaSolver::Gram,
+            cache_covariance: true,
+            idf_floor: 0.1,
+            min_docs_for_lsa: 1,
+        };
+        let mut model = TfIdfLsa::with_config(config.clone());
+        model.fit(
+            &[vec!["今日".to_string()], vec!["明日".to_string()]],
+            HashMap::from([("今日".to_string(), 0), ("明日".to_string(), 1)]),
+        );
+        
+        assert!(model.is_lsa_active());
+        
+        let restored = TfIdfLsa::from_json(&model.to_json().unwrap()).unwrap();
+        assert_eq!(restored.config(), &config);
+        assert_eq!(restored.transform(&["今日".to_string()]), model.transform(&["今日".to_string()]));
+        assert_eq!(TfIdfLsa::new(32).config().embedding_dim, 32);
+        
+        // Models exported before the config existed still load with defaults
+        let legacy = r#"{"vocabulary":{},"idf_weights":[],"lsa_components":null,"embedding_dim":8,"documents_count":0}"#;
+        let legacy_model = TfIdfLsa::from_json(legacy).unwrap();
+        assert_eq!(legacy_model.embedding_dim(), 8);
+        assert!(!legacy_model.is_binary_tf());
+    }
+    
     #[test]
     fn test_model_serialization() {
         let mut model = TfIdfLsa::new(32);
@@ -1,18 +1,37 @@
-use std::collections::{HashMap, HashSet};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use serde::{Deserialize, Serialize};
+use crate::utils::fnv1a_64;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DictionaryEntry {
     pub surface: String,
     pub variants: Vec<String>,
+    // Only match where the text has a word boundary on both sides
+    #[serde(default)]
+    pub whole_word_only: bool,
 }
 
+// Deserialized through new, so the longest-first entry order that find_matches
+// relies on and the variant lookup are re-established whatever the payload holds
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "SerializedUserDictionary")]
 pub struct UserDictionary {
     entries: Vec<DictionaryEntry>,
     variant_to_surface: HashMap<String, String>,
 }
 
+#[derive(Deserialize)]
+struct SerializedUserDictionary {
+    entries: Vec<DictionaryEntry>,
+}
+
+impl From<SerializedUserDictionary> for UserDictionary {
+    fn from(serialized: SerializedUserDictionary) -> Self {
+        Self::new(serialized.entries)
+    }
+}
+
 impl UserDictionary {
     pub fn new(entries: Vec<DictionaryEntry>) -> Self {
         let mut variant_to_surface = HashMap::new();
@@ -34,6 +53,66 @@ impl UserDictionary {
         dict
     }
     
+    // Parse lines of `surface<TAB>variant1,variant2,...[<TAB>whole_word]`. Variants
+    // are optional; blank lines and lines starting with '#' are ignored.
+    pub fn from_tsv(data: &str) -> Result<UserDictionary, String> {
+        let mut entries = Vec::new();
+        
+        for (line_no, line) in data.lines().enumerate() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            
+            let mut columns = line.split('\t');
+            let surface = columns.next().unwrap_or("").trim();
+            let variants = columns.next().unwrap_or("");
+            let whole_word_only = match columns.next().map(str::trim) {
+                None | Some("") => false,
+                Some("whole_word") => true,
+                Some(flag) => return Err(format!("Line {}: unknown flag '{}'", line_no + 1, flag)),
+            };
+            if surface.is_empty() {
+                return Err(format!("Line {}: missing surface form", line_no + 1));
+            }
+            
+            entries.push(DictionaryEntry {
+                surface: surface.to_string(),
+                variants: variants
+                    .split(',')
+                    .map(|v| v.trim())
+                    .filter(|v| !v.is_empty())
+                    .map(|v| v.to_string())
+                    .collect(),
+                whole_word_only,
+            });
+        }
+        
+        Ok(Self::new(entries))
+    }
+    
+    // Inverse of from_tsv
+    pub fn to_tsv(&self) -> String {
+        self.entries
+            .iter()
+            .map(|entry| {
+                let flag = if entry.whole_word_only { "\twhole_word" } else { "" };
+                format!("{}\t{}{}\n", entry.surface, entry.variants.join(","), flag)
+            })
+            .collect()
+    }
+    
+    pub fn entries(&self) -> &[DictionaryEntry] {
+        &self.entries
+    }
+    
+    // All surface forms and variants, sorted
+    pub fn terms(&self) -> Vec<String> {
+        let mut terms: Vec<String> = self.variant_to_surface.keys().cloned().collect();
+        terms.sort();
+        terms
+    }
+    
     fn sort_entries_by_length(&mut self) {
         for entry in &mut self.entries {
             entry.variants.sort_by_key(|v| std::cmp::Reverse(v.chars().count()));
@@ -67,20 +146,21 @@ impl UserDictionary {
                 for pattern in all_patterns {
                     let pattern_chars: Vec<char> = pattern.chars().collect();
                     if i + pattern_chars.len() <= chars.len() {
-                        let text_slice: String = chars[i..i + pattern_chars.len()].iter().collect();
-                        if text_slice == pattern {
-                            let mut all_processed = true;
-                            for j in i..i + pattern_chars.len() {
-                                if processed[j] {
-                                    all_processed = false;
-                                    break;
-                                }
-                            }
+                        let end = i + pattern_chars.len();
+                        let text_slice: String = chars[i..end].iter().collect();
+                        let bounded = !entry.whole_word_only || (
+                            (i == 0 || is_word_boundary(CharType::from_char(chars[i - 1]), chars[i]))
+                                && (end == chars.len() || is_word_boundary(CharType::from_char(chars[end - 1]), chars[end]))
+                        );
+                        if text_slice == pattern && bounded {
+                            let all_processed = !processed[i..i + pattern_chars.len()]
+                                .iter()
+                                .any(|&p| p);
                             
                             if all_processed {
                                 matches.push((i, i + pattern_chars.len(), entry.surface.clone()));
-                                for j in i..i + pattern_chars.len() {
-                                    processed[j] = true;
+                                for flag in &mut processed[i..i + pattern_chars.len()] {
+                                    *flag = true;
                                 }
                                 break;
                             }
@@ -94,6 +174,18 @@ impl UserDictionary {
     }
 }
 
+// How tokens containing digits are treated
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum NumberPolicy {
+    // Keep number tokens as they are
+    #[default]
+    Keep,
+    // Drop any token containing a digit
+    Drop,
+    // Replace every digit run with the sentinel "<NUM>"
+    Normalize,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct JapaneseTokenizer {
     min_ngram: usize,
@@ -104,6 +196,60 @@ pub struct JapaneseTokenizer {
     stop_words: HashSet<String>,
     enable_stop_words: bool,
     pub(crate) user_dictionary: Option<UserDictionary>,
+    // Number of characters skipped by skip-gram bigrams (0 disables them)
+    #[serde(default)]
+    skip_gram_distance: usize,
+    #[serde(default)]
+    number_policy: NumberPolicy,
+    // Generated tokens longer than this many characters are discarded
+    #[serde(default = "default_max_token_chars")]
+    max_token_chars: usize,
+    // Generated tokens shorter than this many characters are discarded
+    #[serde(default = "default_min_token_chars")]
+    min_token_chars: usize,
+    // Only emit n-grams whose characters all share one CharType
+    #[serde(default)]
+    intra_type_ngrams_only: bool,
+    // Emit a radical-class token (e.g. "<RAD:氵>") for each kanji in the radical table
+    #[serde(default)]
+    enable_radical_features: bool,
+    // Emit the kanji stem of inflected verbs and adjectives ("見" from "見ました")
+    #[serde(default)]
+    enable_stemming: bool,
+    // Always in the vocabulary, regardless of frequency filters and size limits
+    #[serde(default)]
+    required_terms: Vec<String>,
+    // Subsampling threshold t: a token in fraction f of the documents is kept with
+    // probability sqrt(t / f) (0 disables)
+    #[serde(default)]
+    subsample_threshold: f32,
+    // Emit each emoji as a single-character token
+    #[serde(default)]
+    keep_emoji: bool,
+    // Remove http(s) URLs before tokenizing
+    #[serde(default)]
+    strip_urls: bool,
+    // Remove @handles before tokenizing
+    #[serde(default)]
+    strip_mentions: bool,
+    // Also emit the n-grams spanning each edge of a dictionary match, which
+    // tokenizing the unmatched segments on their own would lose
+    #[serde(default)]
+    boundary_ngrams: bool,
+    // Shorten stylistic repetition ("すごーーーい", "www") before tokenizing
+    #[serde(default)]
+    collapse_repeats: bool,
+    // Characters (e.g. '・') treated like whitespace, so no token spans them
+    #[serde(default)]
+    extra_separators: Vec<char>,
+}
+
+fn default_max_token_chars() -> usize {
+    64
+}
+
+fn default_min_token_chars() -> usize {
+    1
 }
 
 impl Default for JapaneseTokenizer {
@@ -117,6 +263,21 @@ impl Default for JapaneseTokenizer {
             stop_words: HashSet::new(),
             enable_stop_words: true,
             user_dictionary: None,
+            skip_gram_distance: 0,
+            number_policy: NumberPolicy::Keep,
+            max_token_chars: default_max_token_chars(),
+            min_token_chars: default_min_token_chars(),
+            intra_type_ngrams_only: false,
+            enable_radical_features: false,
+            enable_stemming: false,
+            required_terms: Vec::new(),
+            subsample_threshold: 0.0,
+            keep_emoji: false,
+            strip_urls: false,
+            strip_mentions: false,
+            boundary_ngrams: false,
+            collapse_repeats: false,
+            extra_separators: Vec::new(),
         };
         tokenizer.initialize_stop_words();
         tokenizer
@@ -167,6 +328,10 @@ impl JapaneseTokenizer {
         }
     }
 
+    pub fn builder() -> JapaneseTokenizerBuilder {
+        JapaneseTokenizerBuilder::new()
+    }
+
     pub fn new_with_ngrams(min_ngram: usize, max_ngram: usize) -> Self {
         Self {
             min_ngram,
@@ -182,17 +347,47 @@ impl JapaneseTokenizer {
     pub fn clear_user_dictionary(&mut self) {
         self.user_dictionary = None;
     }
+    
+    // Dictionary surfaces and variants (empty without a dictionary)
+    pub fn dictionary_terms(&self) -> Vec<String> {
+        self.user_dictionary
+            .as_ref()
+            .map(|dictionary| dictionary.terms())
+            .unwrap_or_default()
+    }
 
-    // Generate character n-grams from text
+    // Generate character n-grams from text. N-grams never span whitespace or
+    // punctuation, so "東京 大阪" doesn't produce the spurious "京大".
     pub fn char_ngrams(&self, text: &str) -> Vec<String> {
-        let chars: Vec<char> = text.chars().filter(|c| !c.is_whitespace()).collect();
         let mut ngrams = Vec::new();
-
-        for n in self.min_ngram..=self.max_ngram {
-            if chars.len() >= n {
-                for i in 0..=chars.len() - n {
-                    let ngram: String = chars[i..i + n].iter().collect();
-                    ngrams.push(ngram);
+        
+        // With intra_type_ngrams_only, mixed-type fragments like "見た" are skipped
+        let keep = |gram: &[char]| {
+            !self.intra_type_ngrams_only
+                || gram.windows(2).all(|pair| CharType::from_char(pair[0]) == CharType::from_char(pair[1]))
+        };
+        
+        for segment in text.split(is_segment_boundary).filter(|s| !s.is_empty()) {
+            let chars: Vec<char> = segment.chars().collect();
+            
+            for n in self.min_ngram..=self.max_ngram {
+                if chars.len() >= n {
+                    for i in 0..=chars.len() - n {
+                        if keep(&chars[i..i + n]) {
+                            ngrams.push(chars[i..i + n].iter().collect());
+                        }
+                    }
+                }
+            }
+            
+            // Skip-gram bigrams pair characters with a gap, e.g. "今日は" -> "今は"
+            let span = self.skip_gram_distance + 2;
+            if self.skip_gram_distance > 0 && chars.len() >= span {
+                for i in 0..=chars.len() - span {
+                    let pair = [chars[i], chars[i + span - 1]];
+                    if keep(&pair) {
+                        ngrams.push(pair.iter().collect());
+                    }
                 }
             }
         }
@@ -242,6 +437,169 @@ impl JapaneseTokenizer {
         unigrams
     }
     
+    // Extract whole latin words (runs of letters and digits such as "JavaScript"
+    // or "HTML5"), split on spaces, punctuation and Japanese characters
+    pub fn latin_words(&self, text: &str) -> Vec<String> {
+        let mut words = Vec::new();
+        let mut current_word = String::new();
+        
+        for ch in text.chars() {
+            if matches!(CharType::from_char(ch), CharType::Alphabet | CharType::Number) {
+                current_word.push(ch);
+            } else {
+                if current_word.chars().count() > 1 {
+                    words.push(current_word.clone());
+                }
+                current_word.clear();
+            }
+        }
+        
+        if current_word.chars().count() > 1 {
+            words.push(current_word);
+        }
+        
+        words
+    }
+    
+    // Radical-class tokens for kanji found in the built-in radical table, so kanji
+    // sharing a radical (海 and 河) share a feature. Empty unless
+    // enable_radical_features is set.
+    pub fn radical_tokens(&self, text: &str) -> Vec<String> {
+        if !self.enable_radical_features {
+            return Vec::new();
+        }
+        
+        text.chars()
+            .filter_map(kanji_radical)
+            .map(|radical| format!("<RAD:{}>", radical))
+            .collect()
+    }
+    
+    // Kanji stems of inflected words: a kanji run followed by hiragana starting with
+    // a common inflection ending ("見ました", "見る", "高かった"). Heuristic, not
+    // morphological. Empty unless enable_stemming is set.
+    pub fn stem_tokens(&self, text: &str) -> Vec<String> {
+        if !self.enable_stemming {
+            return Vec::new();
+        }
+        
+        let chars: Vec<char> = text.chars().collect();
+        let mut stems = Vec::new();
+        let mut i = 0;
+        while i < chars.len() {
+            if !matches!(CharType::from_char(chars[i]), CharType::Kanji) {
+                i += 1;
+                continue;
+            }
+            
+            let start = i;
+            while i < chars.len() && matches!(CharType::from_char(chars[i]), CharType::Kanji) {
+                i += 1;
+            }
+            let ending: String = chars[i..]
+                .iter()
+                .take_while(|&&ch| matches!(CharType::from_char(ch), CharType::Hiragana))
+                .collect();
+            if INFLECTION_ENDINGS.iter().any(|suffix| ending.starts_with(suffix)) {
+                stems.push(chars[start..i].iter().collect());
+            }
+        }
+        
+        stems
+    }
+    
+    // Each emoji as its own token, so sentiment-bearing emoji take part in
+    // similarity. Multi-codepoint sequences (ZWJ, skin tones) yield their parts.
+    // Empty unless keep_emoji is set.
+    pub fn emoji_tokens(&self, text: &str) -> Vec<String> {
+        if !self.keep_emoji {
+            return Vec::new();
+        }
+        
+        text.chars().filter(|&ch| is_emoji(ch)).map(String::from).collect()
+    }
+    
+    // Text cleanup applied before any tokenization method runs
+    fn prepare_text<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut text = self.strip_urls_and_mentions(text);
+        let collapsed = match self.collapse_repeated_chars(&text) {
+            Cow::Owned(collapsed) => Some(collapsed),
+            Cow::Borrowed(_) => None,
+        };
+        if let Some(collapsed) = collapsed {
+            text = Cow::Owned(collapsed);
+        }
+        
+        if !self.extra_separators.is_empty() {
+            text = Cow::Owned(
+                text.chars()
+                    .map(|ch| if self.extra_separators.contains(&ch) { ' ' } else { ch })
+                    .collect(),
+            );
+        }
+        text
+    }
+    
+    // With collapse_repeats, runs of long-vowel marks (ー, 〜, ～) become a single
+    // mark and runs of 3+ of any other character become two ("www" -> "ww"), so
+    // elongated spellings share tokens while genuine doubles ("はは") survive
+    pub fn collapse_repeated_chars<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        if !self.collapse_repeats {
+            return Cow::Borrowed(text);
+        }
+        
+        let mut result = String::with_capacity(text.len());
+        let mut prev: Option<char> = None;
+        let mut run = 0;
+        for ch in text.chars() {
+            run = if prev == Some(ch) { run + 1 } else { 1 };
+            prev = Some(ch);
+            let limit = if matches!(ch, 'ー' | '〜' | '～') { 1 } else { 2 };
+            if run <= limit {
+                result.push(ch);
+            }
+        }
+        Cow::Owned(result)
+    }
+    
+    // Replace URLs and @mentions with a space (as enabled by strip_urls and
+    // strip_mentions) so they yield no tokens and text around them isn't joined.
+    // A URL runs from "http://" or "https://" to the next whitespace or non-ASCII
+    // character; a mention is '@' plus ASCII letters, digits and '_', unless the
+    // '@' follows a letter or digit (as in an e-mail address).
+    pub fn strip_urls_and_mentions<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        if !self.strip_urls && !self.strip_mentions {
+            return Cow::Borrowed(text);
+        }
+        
+        let mut result = String::with_capacity(text.len());
+        let mut prev: Option<char> = None;
+        let mut rest = text;
+        while let Some(ch) = rest.chars().next() {
+            let span_len = if self.strip_urls && (rest.starts_with("http://") || rest.starts_with("https://")) {
+                rest.find(|c: char| !c.is_ascii_graphic()).unwrap_or(rest.len())
+            } else if self.strip_mentions && ch == '@' && !prev.is_some_and(|p| p.is_alphanumeric()) {
+                let handle_len = rest[1..]
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(rest.len() - 1);
+                if handle_len > 0 { handle_len + 1 } else { 0 }
+            } else {
+                0
+            };
+            
+            if span_len > 0 {
+                result.push(' ');
+                prev = Some(' ');
+                rest = &rest[span_len..];
+            } else {
+                result.push(ch);
+                prev = Some(ch);
+                rest = &rest[ch.len_utf8()..];
+            }
+        }
+        Cow::Owned(result)
+    }
+    
     // Simple word boundary estimation
     pub fn estimate_word_boundaries(&self, text: &str) -> Vec<String> {
         let mut words = Vec::new();
@@ -252,16 +610,7 @@ impl JapaneseTokenizer {
             let char_type = CharType::from_char(ch);
 
             // Detect boundaries
-            let is_boundary = match (prev_type, char_type) {
-                (CharType::Hiragana, CharType::Kanji) => true,
-                (CharType::Katakana, CharType::Kanji) => true,
-                (CharType::Kanji, CharType::Hiragana) => {
-                    // Common particles following kanji
-                    matches!(ch, 'を' | 'は' | 'が' | 'に' | 'で' | 'と' | 'の' | 'へ' | 'や')
-                }
-                (_, CharType::Other) | (CharType::Other, _) => true,
-                _ => false,
-            };
+            let is_boundary = is_word_boundary(prev_type, ch);
 
             if is_boundary && !current_word.is_empty() {
                 if current_word.len() > 1 {
@@ -283,101 +632,236 @@ impl JapaneseTokenizer {
         words
     }
 
-    // Main tokenization function combining all methods
+    // Main tokenization function combining all methods. A token occurring several
+    // times is repeated accordingly (see term_frequencies) so TF stays meaningful;
+    // tokens are sorted so the output doesn't depend on HashMap iteration order.
     pub fn tokenize(&self, text: &str) -> Vec<String> {
-        let mut tokens = HashSet::new();
-
-        // If user dictionary is available, find matches first
-        if let Some(ref dictionary) = self.user_dictionary {
-            let matches = dictionary.find_matches(text);
-            
-            // Add dictionary matches as tokens
-            for (_start, _end, surface) in &matches {
-                tokens.insert(surface.clone());
+        let mut frequencies: Vec<(String, usize)> = self.term_frequencies(text).into_iter().collect();
+        frequencies.sort();
+        
+        frequencies
+            .into_iter()
+            .flat_map(|(token, count)| std::iter::repeat_n(token, count))
+            .collect()
+    }
+    
+    // Same tokens as tokenize, ordered by where they first occur in the text.
+    // Tokens that don't appear literally (normalized numbers, dictionary surfaces
+    // matched through a variant) come last.
+    pub fn tokenize_ordered(&self, text: &str) -> Vec<String> {
+        let mut frequencies: Vec<(usize, String, usize)> = self
+            .term_frequencies(text)
+            .into_iter()
+            .map(|(token, count)| (text.find(token.as_str()).unwrap_or(usize::MAX), token, count))
+            .collect();
+        frequencies.sort();
+        
+        frequencies
+            .into_iter()
+            .flat_map(|(_, token, count)| std::iter::repeat_n(token, count))
+            .collect()
+    }
+    
+    // Tokenize and count how often each token occurs. A token found by several
+    // extraction methods (e.g. as both an n-gram and a char-type run) counts the
+    // occurrences of the method that found it most often, not their sum.
+    pub fn term_frequencies(&self, text: &str) -> HashMap<String, usize> {
+        let mut frequencies = HashMap::new();
+        let stripped = self.prepare_text(text);
+        let (surfaces, segments) = self.split_dictionary_matches(stripped.as_ref());
+        
+        // Add dictionary matches as tokens
+        for surface in surfaces {
+            *frequencies.entry(surface).or_insert(0) += 1;
+        }
+        
+        // Apply regular tokenization to unmatched segments
+        for segment in segments {
+            self.count_segment_tokens(&segment, &mut frequencies);
+        }
+        
+        for token in self.junction_ngrams(stripped.as_ref()).into_iter().filter_map(|token| self.apply_number_policy(token)) {
+            if self.is_token_kept(&token) {
+                *frequencies.entry(token).or_insert(0) += 1;
             }
-            
-            // Process unmatched portions with regular tokenization
-            let chars: Vec<char> = text.chars().collect();
-            let mut processed = vec![false; chars.len()];
-            
-            // Mark matched regions as processed
-            for (start, end, _) in &matches {
-                for i in *start..*end {
-                    processed[i] = true;
+        }
+
+        frequencies
+    }
+    
+    // N-grams that cross the start or end of a dictionary match, i.e. contain
+    // characters from both the match and the text next to it. Empty unless
+    // boundary_ngrams is set and a dictionary is loaded.
+    pub fn junction_ngrams(&self, text: &str) -> Vec<String> {
+        let (true, Some(dictionary)) = (self.boundary_ngrams, self.user_dictionary.as_ref()) else {
+            return Vec::new();
+        };
+        let chars: Vec<char> = text.chars().collect();
+        let junctions: BTreeSet<usize> = dictionary
+            .find_matches(text)
+            .into_iter()
+            .flat_map(|(start, end, _)| [start, end])
+            .filter(|&junction| junction > 0 && junction < chars.len())
+            .collect();
+        
+        let mut ngrams = Vec::new();
+        for junction in junctions {
+            for n in self.min_ngram.max(2)..=self.max_ngram {
+                let first = junction.saturating_sub(n - 1);
+                for start in first..junction {
+                    let gram = match chars.get(start..start + n) {
+                        Some(gram) => gram,
+                        None => break,
+                    };
+                    if !gram.iter().any(|&ch| is_segment_boundary(ch)) {
+                        ngrams.push(gram.iter().collect());
+                    }
                 }
             }
-            
-            // Extract unmatched segments
-            let mut segments = Vec::new();
-            let mut current_segment = String::new();
-            
-            for (i, ch) in chars.iter().enumerate() {
-                if !processed[i] {
-                    current_segment.push(*ch);
-                } else if !current_segment.is_empty() {
-                    segments.push(current_segment.clone());
-                    current_segment.clear();
-                }
+        }
+        ngrams
+    }
+    
+    // Every candidate token of the text (sorted, each once) with whether the
+    // stop-word, length or number rules filter it out, and its quality score
+    // (calculate_token_score without the IDF factor). Dictionary matches are never
+    // filtered. Tokens dropped by NumberPolicy::Drop are listed as filtered.
+    pub fn tokenize_debug(&self, text: &str) -> Vec<(String, bool, f32)> {
+        let stripped = self.prepare_text(text);
+        let (surfaces, segments) = self.split_dictionary_matches(stripped.as_ref());
+        
+        let mut candidates: BTreeMap<String, bool> = surfaces.into_iter().map(|surface| (surface, false)).collect();
+        let junction_ngrams = self.junction_ngrams(stripped.as_ref());
+        for tokens in segments.iter().flat_map(|segment| self.extraction_methods(segment)).chain([junction_ngrams]) {
+            for token in tokens {
+                let (token, filtered) = match self.apply_number_policy(token.clone()) {
+                    Some(token) => {
+                        let filtered = !self.is_token_kept(&token);
+                        (token, filtered)
+                    }
+                    None => (token, true),
+                };
+                candidates.entry(token).or_insert(filtered);
             }
-            
-            if !current_segment.is_empty() {
-                segments.push(current_segment);
+        }
+        
+        candidates
+            .into_iter()
+            .map(|(token, filtered)| {
+                let score = self.token_quality(&token);
+                (token, filtered, score)
+            })
+            .collect()
+    }
+    
+    // Dictionary matches (as surfaces) and the unmatched stretches of text between
+    // them. Without a dictionary the whole text is one unmatched segment.
+    fn split_dictionary_matches(&self, text: &str) -> (Vec<String>, Vec<String>) {
+        let Some(ref dictionary) = self.user_dictionary else {
+            return (Vec::new(), vec![text.to_string()]);
+        };
+        let matches = dictionary.find_matches(text);
+        
+        // Mark matched regions as processed
+        let chars: Vec<char> = text.chars().collect();
+        let mut processed = vec![false; chars.len()];
+        for (start, end, _) in &matches {
+            for flag in &mut processed[*start..*end] {
+                *flag = true;
             }
-            
-            // Apply regular tokenization to unmatched segments
-            for segment in segments {
-                for token in self.char_ngrams(&segment) {
-                    if !self.should_filter_token(&token) {
-                        tokens.insert(token);
-                    }
-                }
-                
-                for token in self.kanji_unigrams(&segment) {
-                    if !self.should_filter_token(&token) {
-                        tokens.insert(token);
-                    }
-                }
-                
-                for token in self.char_type_sequences(&segment) {
-                    if !self.should_filter_token(&token) {
-                        tokens.insert(token);
-                    }
-                }
-                
-                for token in self.estimate_word_boundaries(&segment) {
-                    if !self.should_filter_token(&token) {
-                        tokens.insert(token);
-                    }
-                }
+        }
+        
+        // Extract unmatched segments
+        let mut segments = Vec::new();
+        let mut current_segment = String::new();
+        
+        for (i, ch) in chars.iter().enumerate() {
+            if !processed[i] {
+                current_segment.push(*ch);
+            } else if !current_segment.is_empty() {
+                segments.push(current_segment.clone());
+                current_segment.clear();
             }
-        } else {
-            // No dictionary, use regular tokenization
-            for token in self.char_ngrams(text) {
-                if !self.should_filter_token(&token) {
-                    tokens.insert(token);
+        }
+        
+        if !current_segment.is_empty() {
+            segments.push(current_segment);
+        }
+        
+        let surfaces = matches.into_iter().map(|(_, _, surface)| surface).collect();
+        (surfaces, segments)
+    }
+    
+    // Raw output of each tokenization method, before any filtering
+    fn extraction_methods(&self, text: &str) -> [Vec<String>; 8] {
+        [
+            self.char_ngrams(text),
+            self.kanji_unigrams(text),
+            self.radical_tokens(text),
+            self.stem_tokens(text),
+            self.emoji_tokens(text),
+            self.char_type_sequences(text),
+            self.estimate_word_boundaries(text),
+            self.latin_words(text),
+        ]
+    }
+    
+    // Length limits and stop words (dictionary matches bypass this)
+    fn is_token_kept(&self, token: &str) -> bool {
+        let length = token.chars().count();
+        length >= self.min_token_chars && length <= self.max_token_chars && !self.should_filter_token(token)
+    }
+    
+    // Apply all tokenization methods to a piece of text and add the counts of
+    // unfiltered tokens (maximum over methods, see term_frequencies)
+    fn count_segment_tokens(&self, text: &str, frequencies: &mut HashMap<String, usize>) {
+        let mut segment_counts: HashMap<String, usize> = HashMap::new();
+        for tokens in self.extraction_methods(text) {
+            let mut method_counts: HashMap<String, usize> = HashMap::new();
+            for token in tokens.into_iter().filter_map(|token| self.apply_number_policy(token)) {
+                if self.is_token_kept(&token) {
+                    *method_counts.entry(token).or_insert(0) += 1;
                 }
             }
             
-            for token in self.kanji_unigrams(text) {
-                if !self.should_filter_token(&token) {
-                    tokens.insert(token);
-                }
+            for (token, count) in method_counts {
+                let entry = segment_counts.entry(token).or_insert(0);
+                *entry = (*entry).max(count);
             }
-
-            for token in self.char_type_sequences(text) {
-                if !self.should_filter_token(&token) {
-                    tokens.insert(token);
-                }
-            }
-
-            for token in self.estimate_word_boundaries(text) {
-                if !self.should_filter_token(&token) {
-                    tokens.insert(token);
+        }
+        
+        for (token, count) in segment_counts {
+            *frequencies.entry(token).or_insert(0) += count;
+        }
+    }
+    
+    // Drop or normalize digits in a token according to number_policy
+    fn apply_number_policy(&self, token: String) -> Option<String> {
+        let is_digit = |c: char| matches!(CharType::from_char(c), CharType::Number);
+        if self.number_policy == NumberPolicy::Keep || !token.chars().any(is_digit) {
+            return Some(token);
+        }
+        
+        match self.number_policy {
+            NumberPolicy::Keep => Some(token),
+            NumberPolicy::Drop => None,
+            NumberPolicy::Normalize => {
+                let mut normalized = String::new();
+                let mut in_number = false;
+                for ch in token.chars() {
+                    if is_digit(ch) {
+                        if !in_number {
+                            normalized.push_str("<NUM>");
+                        }
+                        in_number = true;
+                    } else {
+                        normalized.push(ch);
+                        in_number = false;
+                    }
                 }
+                Some(normalized)
             }
         }
-
-        tokens.into_iter().collect()
     }
 
     // Check if a token should be filtered
@@ -402,6 +886,14 @@ impl JapaneseTokenizer {
 
     // Calculate token quality score (for N-gram quality scoring)
     pub fn calculate_token_score(&self, token: &str, doc_freq: usize, total_docs: usize) -> f32 {
+        // TF-IDF inspired scoring
+        let idf = (total_docs as f32 / doc_freq as f32).ln();
+        self.token_quality(token) * idf
+    }
+    
+    // Corpus-independent part of calculate_token_score: dictionary, single-kanji,
+    // particle and character-type adjustments
+    fn token_quality(&self, token: &str) -> f32 {
         let mut score = 1.0;
         
         // Check if token is a dictionary word (high priority)
@@ -446,53 +938,107 @@ impl JapaneseTokenizer {
             score *= 1.2;
         }
         
-        // TF-IDF inspired scoring
-        let idf = (total_docs as f32 / doc_freq as f32).ln();
-        score *= idf;
-        
         score
     }
 
     // Build vocabulary from multiple documents with quality scoring
     pub fn build_vocabulary(&self, documents: &[String]) -> HashMap<String, usize> {
+        let tokenized: Vec<Vec<String>> = documents.iter().map(|doc| self.tokenize(doc)).collect();
+        self.build_vocabulary_from_tokens(&tokenized)
+    }
+    
+    // Same as build_vocabulary for documents that are already tokenized
+    pub fn build_vocabulary_from_tokens(&self, tokenized_documents: &[Vec<String>]) -> HashMap<String, usize> {
+        self.ranked_vocabulary(tokenized_documents)
+            .into_iter()
+            .enumerate()
+            .map(|(idx, token)| (token, idx))
+            .collect()
+    }
+    
+    // Like build_vocabulary_from_tokens, but tokens already in previous keep their
+    // index and new tokens are appended after the highest previous index. Indices
+    // of tokens that were filtered out are left unused, so the result may have gaps.
+    pub fn build_vocabulary_stable(
+        &self,
+        tokenized_documents: &[Vec<String>],
+        previous: &HashMap<String, usize>,
+    ) -> HashMap<String, usize> {
+        let mut next_idx = previous.values().max().map_or(0, |&idx| idx + 1);
+        self.ranked_vocabulary(tokenized_documents)
+            .into_iter()
+            .map(|token| {
+                let idx = previous.get(&token).copied().unwrap_or_else(|| {
+                    next_idx += 1;
+                    next_idx - 1
+                });
+                (token, idx)
+            })
+            .collect()
+    }
+    
+    // Tokens that pass the document frequency filters, best score first
+    fn ranked_vocabulary(&self, tokenized_documents: &[Vec<String>]) -> Vec<String> {
         let mut doc_freq: HashMap<String, usize> = HashMap::new();
         
-        for doc in documents {
-            let tokens: HashSet<String> = self.tokenize(doc).into_iter().collect();
+        for tokens in tokenized_documents {
+            let tokens: HashSet<&String> = tokens.iter().collect();
             for token in tokens {
-                *doc_freq.entry(token).or_insert(0) += 1;
+                *doc_freq.entry(token.clone()).or_insert(0) += 1;
             }
         }
 
-        let total_docs = documents.len();
+        let total_docs = tokenized_documents.len();
         let max_docs = ((total_docs as f32 * self.max_doc_freq_ratio) as usize).max(1);
         
         // Filter and score tokens
         let mut scored_vocab: Vec<(String, f32)> = doc_freq
             .iter()
             .filter(|(_, freq)| **freq >= self.min_doc_freq && **freq <= max_docs)
+            .filter(|(token, freq)| self.survives_subsampling(token, **freq as f32 / total_docs as f32))
             .map(|(token, freq)| {
                 let score = self.calculate_token_score(token, *freq, total_docs);
                 (token.clone(), score)
             })
             .collect();
 
-        // Sort by quality score instead of just frequency
-        scored_vocab.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+        // Sort by quality score instead of just frequency. Ties are broken by the
+        // token itself so index assignment doesn't depend on HashMap iteration order;
+        // total_cmp keeps a NaN score from panicking the sort.
+        scored_vocab.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
         
         // Dynamic vocabulary size based on document count
         let dynamic_vocab_size = self.calculate_dynamic_vocab_size(total_docs);
         scored_vocab.truncate(dynamic_vocab_size);
 
-        // Create token to index mapping
-        let mut vocab = HashMap::new();
-        for (idx, (token, _)) in scored_vocab.into_iter().enumerate() {
-            vocab.insert(token, idx);
-        }
-
+        let mut vocab: Vec<String> = scored_vocab.into_iter().map(|(token, _)| token).collect();
+        
+        // Required terms that didn't make the cut go last
+        let selected: HashSet<String> = vocab.iter().cloned().collect();
+        let mut missing: Vec<&String> = self.required_terms
+            .iter()
+            .filter(|term| !selected.contains(*term))
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        missing.sort();
+        vocab.extend(missing.into_iter().cloned());
+        
         vocab
     }
 
+    // Word2vec-style subsampling of very frequent tokens. The keep/drop draw is a
+    // hash of the token, so the same corpus always yields the same vocabulary.
+    fn survives_subsampling(&self, token: &str, doc_fraction: f32) -> bool {
+        if self.subsample_threshold <= 0.0 || doc_fraction <= self.subsample_threshold {
+            return true;
+        }
+        
+        let keep_probability = (self.subsample_threshold / doc_fraction).sqrt() as f64;
+        let draw = (fnv1a_64(&[token.as_bytes()]) >> 11) as f64 / (1u64 << 53) as f64;
+        draw < keep_probability
+    }
+    
     // Calculate dynamic vocabulary size based on document count
     fn calculate_dynamic_vocab_size(&self, doc_count: usize) -> usize {
         // Base size: 100 tokens per document, capped at max_vocab_size
@@ -509,14 +1055,76 @@ impl JapaneseTokenizer {
     }
 
     // Setter methods for configuration
+    pub fn set_ngram_range(&mut self, min_ngram: usize, max_ngram: usize) -> Result<(), String> {
+        if min_ngram == 0 || min_ngram > max_ngram {
+            return Err(format!(
+                "ngram range must satisfy 1 <= min <= max, got ({}, {})",
+                min_ngram, max_ngram
+            ));
+        }
+        self.min_ngram = min_ngram;
+        self.max_ngram = max_ngram;
+        Ok(())
+    }
+    
+    pub fn set_min_doc_freq(&mut self, min_doc_freq: usize) -> Result<(), String> {
+        if min_doc_freq == 0 {
+            return Err("min_doc_freq must be at least 1".to_string());
+        }
+        self.min_doc_freq = min_doc_freq;
+        Ok(())
+    }
+    
+    pub fn set_max_doc_freq_ratio(&mut self, ratio: f32) -> Result<(), String> {
+        if !(ratio > 0.0 && ratio <= 1.0) {
+            return Err(format!("max_doc_freq_ratio must be in (0, 1], got {}", ratio));
+        }
+        self.max_doc_freq_ratio = ratio;
+        Ok(())
+    }
+    
+    // Softer alternative to max_doc_freq_ratio; tokens in at most this fraction of
+    // the documents are always kept
+    pub fn set_subsample_threshold(&mut self, threshold: f32) -> Result<(), String> {
+        if !(0.0..=1.0).contains(&threshold) {
+            return Err(format!("subsample_threshold must be in [0, 1], got {}", threshold));
+        }
+        self.subsample_threshold = threshold;
+        Ok(())
+    }
+    
+    pub fn set_max_vocab_size(&mut self, max_vocab_size: usize) -> Result<(), String> {
+        if max_vocab_size == 0 {
+            return Err("max_vocab_size must be at least 1".to_string());
+        }
+        self.max_vocab_size = max_vocab_size;
+        Ok(())
+    }
+    
     pub fn set_stop_words_enabled(&mut self, enabled: bool) {
         self.enable_stop_words = enabled;
     }
     
+    // Terms build_vocabulary always includes. Their IDF is computed as usual, so a
+    // required term that never occurs carries no weight.
+    pub fn set_required_terms(&mut self, terms: Vec<String>) {
+        self.required_terms = terms;
+    }
+    
     pub fn add_stop_word(&mut self, word: &str) {
         self.stop_words.insert(word.to_string());
     }
     
+    // Add stop words from text with one word per line (blank lines and '#' comments are skipped)
+    pub fn load_stop_words_from_text(&mut self, data: &str) {
+        for line in data.lines() {
+            let word = line.trim();
+            if !word.is_empty() && !word.starts_with('#') {
+                self.stop_words.insert(word.to_string());
+            }
+        }
+    }
+    
     pub fn remove_stop_word(&mut self, word: &str) {
         self.stop_words.remove(word);
     }
@@ -524,6 +1132,323 @@ impl JapaneseTokenizer {
     pub fn get_stop_words(&self) -> &HashSet<String> {
         &self.stop_words
     }
+    
+    pub fn set_skip_gram_distance(&mut self, distance: usize) {
+        self.skip_gram_distance = distance;
+    }
+    
+    // Whether another tokenizer produces tokens in the same way (n-gram range,
+    // stop-word handling, skip-grams, number policy, token length limits and
+    // n-gram type restriction, radical features, stemming and emoji)
+    pub fn is_compatible_with(&self, other: &JapaneseTokenizer) -> bool {
+        self.min_ngram == other.min_ngram
+            && self.max_ngram == other.max_ngram
+            && self.enable_stop_words == other.enable_stop_words
+            && (!self.enable_stop_words || self.stop_words == other.stop_words)
+            && self.skip_gram_distance == other.skip_gram_distance
+            && self.number_policy == other.number_policy
+            && self.max_token_chars == other.max_token_chars
+            && self.min_token_chars == other.min_token_chars
+            && self.intra_type_ngrams_only == other.intra_type_ngrams_only
+            && self.enable_radical_features == other.enable_radical_features
+            && self.enable_stemming == other.enable_stemming
+            && self.keep_emoji == other.keep_emoji
+            && self.strip_urls == other.strip_urls
+            && self.strip_mentions == other.strip_mentions
+            && self.boundary_ngrams == other.boundary_ngrams
+            && self.collapse_repeats == other.collapse_repeats
+            && self.extra_separators == other.extra_separators
+    }
+    
+    pub fn set_number_policy(&mut self, policy: NumberPolicy) {
+        self.number_policy = policy;
+    }
+    
+    // Cap on generated token length in characters (dictionary matches are exempt)
+    pub fn set_max_token_chars(&mut self, max_token_chars: usize) -> Result<(), String> {
+        if max_token_chars == 0 {
+            return Err("max_token_chars must be at least 1".to_string());
+        }
+        self.max_token_chars = max_token_chars;
+        Ok(())
+    }
+    
+    pub fn max_token_chars(&self) -> usize {
+        self.max_token_chars
+    }
+    
+    // Minimum generated token length in characters, applied even when stop words
+    // are disabled (dictionary matches are exempt)
+    pub fn set_min_token_chars(&mut self, min_token_chars: usize) {
+        self.min_token_chars = min_token_chars;
+    }
+    
+    pub fn min_token_chars(&self) -> usize {
+        self.min_token_chars
+    }
+    
+    pub fn set_intra_type_ngrams_only(&mut self, enabled: bool) {
+        self.intra_type_ngrams_only = enabled;
+    }
+    
+    pub fn set_radical_features(&mut self, enabled: bool) {
+        self.enable_radical_features = enabled;
+    }
+    
+    pub fn set_stemming(&mut self, enabled: bool) {
+        self.enable_stemming = enabled;
+    }
+    
+    pub fn set_keep_emoji(&mut self, enabled: bool) {
+        self.keep_emoji = enabled;
+    }
+    
+    pub fn set_strip_urls(&mut self, enabled: bool) {
+        self.strip_urls = enabled;
+    }
+    
+    pub fn set_strip_mentions(&mut self, enabled: bool) {
+        self.strip_mentions = enabled;
+    }
+    
+    pub fn set_boundary_ngrams(&mut self, enabled: bool) {
+        self.boundary_ngrams = enabled;
+    }
+    
+    pub fn set_collapse_repeats(&mut self, enabled: bool) {
+        self.collapse_repeats = enabled;
+    }
+    
+    // Punctuation to split tokens at, on top of whitespace and the punctuation
+    // that always separates (see is_segment_boundary)
+    pub fn set_extra_separators(&mut self, separators: Vec<char>) {
+        self.extra_separators = separators;
+    }
+}
+
+// Hiragana that start the inflected ending of a verb or adjective (polite, past,
+// te-form, negative, dictionary-form endings). Particles like は/が/を/に/で/の are
+// deliberately absent.
+const INFLECTION_ENDINGS: &[&str] = &[
+    "ます", "まし", "ませ", "ない", "なかっ", "た", "て", "る", "れ", "られ", "り", "っ",
+    "く", "い", "かっ", "けれ", "う", "す", "つ", "む", "ん", "さ", "せ", "ろ", "しい",
+    "しく", "した", "して", "する", "しま", "しな",
+];
+
+// Semantic radical of common kanji. Deliberately small: only radicals that carry
+// a clear meaning (water, tree, person, hand, speech, ...) are listed.
+fn kanji_radical(ch: char) -> Option<char> {
+    let radical = match ch {
+        '海' | '河' | '湖' | '池' | '波' | '流' | '泳' | '洗' | '港' | '湾' | '沖' | '沼'
+        | '浜' | '潮' | '源' | '漁' | '液' | '温' | '涼' | '浴' | '深' | '浅' => '氵',
+        '林' | '森' | '村' | '板' | '柱' | '根' | '桜' | '梅' | '松' | '杉' | '橋' | '枝'
+        | '株' | '植' | '樹' | '材' | '棒' => '木',
+        '休' | '体' | '作' | '住' | '使' | '信' | '働' | '健' | '仕' | '代' | '他' | '伝'
+        | '例' | '係' | '借' | '優' => '亻',
+        '持' | '指' | '打' | '投' | '折' | '拾' | '押' | '探' | '接' | '推' | '提' | '技'
+        | '招' | '捕' | '採' | '操' | '撮' => '扌',
+        '話' | '語' | '読' | '説' | '記' | '計' | '試' | '詩' | '調' | '談' | '論' | '講'
+        | '議' | '認' | '訪' | '訳' | '評' => '言',
+        '紙' | '線' | '級' | '組' | '絵' | '続' | '結' | '終' | '細' | '経' | '給' | '約'
+        | '編' | '緑' | '練' | '網' | '織' => '糸',
+        '花' | '茶' | '草' | '葉' | '薬' | '菜' | '芸' | '芽' | '菊' | '苗' => '艹',
+        '鉄' | '銀' | '銅' | '鏡' | '鉱' | '針' | '鋼' | '鍋' | '鐘' => '金',
+        '時' | '晴' | '明' | '暗' | '曜' | '映' | '晩' | '暖' | '暑' | '昼' => '日',
+        '焼' | '燃' | '灯' | '炎' | '煙' | '爆' | '炊' => '火',
+        '情' | '性' | '快' | '慣' | '怖' | '悩' | '惜' => '忄',
+        '飯' | '飲' | '館' | '飼' | '飽' => '飠',
+        '地' | '場' | '坂' | '城' | '域' | '境' | '塩' | '堤' | '塔' => '土',
+        '岩' | '岸' | '峰' | '島' | '崎' | '峠' => '山',
+        '雪' | '雲' | '電' | '雷' | '震' | '霧' | '露' => '雨',
+        _ => return None,
+    };
+    Some(radical)
+}
+
+// Whether a word boundary falls before ch when the previous character has type prev_type
+fn is_word_boundary(prev_type: CharType, ch: char) -> bool {
+    match (prev_type, CharType::from_char(ch)) {
+        (CharType::Hiragana, CharType::Kanji) => true,
+        (CharType::Katakana, CharType::Kanji) => true,
+        (CharType::Kanji, CharType::Hiragana) => {
+            // Common particles following kanji
+            matches!(ch, 'を' | 'は' | 'が' | 'に' | 'で' | 'と' | 'の' | 'へ' | 'や')
+        }
+        (_, CharType::Other) | (CharType::Other, _) => true,
+        _ => false,
+    }
+}
+
+fn is_emoji(ch: char) -> bool {
+    matches!(ch,
+        '\u{1F300}'..='\u{1F5FF}'     // Symbols and pictographs
+        | '\u{1F600}'..='\u{1F64F}'   // Emoticons
+        | '\u{1F680}'..='\u{1F6FF}'   // Transport and map symbols
+        | '\u{1F900}'..='\u{1F9FF}'   // Supplemental symbols and pictographs
+        | '\u{1FA70}'..='\u{1FAFF}'   // Symbols and pictographs extended-A
+        | '\u{1F1E6}'..='\u{1F1FF}'   // Regional indicators (flags)
+        | '\u{2600}'..='\u{27BF}')    // Miscellaneous symbols and dingbats
+}
+
+// Whitespace and punctuation (ASCII, CJK and fullwidth) that n-grams must not
+// span. Iteration marks and 〇 (U+3005-U+3007) are part of words, not boundaries.
+pub(crate) fn is_segment_boundary(ch: char) -> bool {
+    ch.is_whitespace()
+        || ch.is_ascii_punctuation()
+        || matches!(ch,
+            '\u{2000}'..='\u{206F}'     // General punctuation (…, “”, ‥)
+            | '\u{3000}'..='\u{3004}'   // Ideographic space, 、。〃
+            | '\u{3008}'..='\u{303F}'   // Brackets 「」『』【】 and other CJK symbols
+            | '\u{FF01}'..='\u{FF0F}'   // Fullwidth ！＂＃...／
+            | '\u{FF1A}'..='\u{FF20}'   // Fullwidth ：；＜＝＞？＠
+            | '\u{FF3B}'..='\u{FF40}'   // Fullwidth ［＼］＾＿｀
+            | '\u{FF5B}'..='\u{FF65}')  // Fullwidth ｛｜｝～ and halfwidth 。「」、・
+}
+
+// Chainable configuration for JapaneseTokenizer, starting from the defaults
+#[derive(Debug, Clone, Default)]
+pub struct JapaneseTokenizerBuilder {
+    tokenizer: JapaneseTokenizer,
+}
+
+impl JapaneseTokenizerBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    pub fn ngram_range(mut self, min_ngram: usize, max_ngram: usize) -> Self {
+        self.tokenizer.min_ngram = min_ngram;
+        self.tokenizer.max_ngram = max_ngram;
+        self
+    }
+    
+    pub fn min_doc_freq(mut self, min_doc_freq: usize) -> Self {
+        self.tokenizer.min_doc_freq = min_doc_freq;
+        self
+    }
+    
+    pub fn max_doc_freq_ratio(mut self, ratio: f32) -> Self {
+        self.tokenizer.max_doc_freq_ratio = ratio;
+        self
+    }
+    
+    pub fn max_vocab_size(mut self, max_vocab_size: usize) -> Self {
+        self.tokenizer.max_vocab_size = max_vocab_size;
+        self
+    }
+    
+    pub fn required_terms(mut self, terms: Vec<String>) -> Self {
+        self.tokenizer.required_terms = terms;
+        self
+    }
+    
+    pub fn subsample_threshold(mut self, threshold: f32) -> Self {
+        self.tokenizer.subsample_threshold = threshold;
+        self
+    }
+    
+    // Replace the built-in stop-word list
+    pub fn stop_words<I, S>(mut self, words: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.tokenizer.stop_words = words.into_iter().map(Into::into).collect();
+        self
+    }
+    
+    pub fn stop_words_enabled(mut self, enabled: bool) -> Self {
+        self.tokenizer.enable_stop_words = enabled;
+        self
+    }
+    
+    pub fn dictionary(mut self, entries: Vec<DictionaryEntry>) -> Self {
+        self.tokenizer.set_user_dictionary(entries);
+        self
+    }
+    
+    pub fn skip_gram_distance(mut self, distance: usize) -> Self {
+        self.tokenizer.skip_gram_distance = distance;
+        self
+    }
+    
+    pub fn number_policy(mut self, policy: NumberPolicy) -> Self {
+        self.tokenizer.number_policy = policy;
+        self
+    }
+    
+    pub fn max_token_chars(mut self, max_token_chars: usize) -> Self {
+        self.tokenizer.max_token_chars = max_token_chars;
+        self
+    }
+    
+    pub fn min_token_chars(mut self, min_token_chars: usize) -> Self {
+        self.tokenizer.min_token_chars = min_token_chars;
+        self
+    }
+    
+    pub fn intra_type_ngrams_only(mut self, enabled: bool) -> Self {
+        self.tokenizer.intra_type_ngrams_only = enabled;
+        self
+    }
+    
+    pub fn radical_features(mut self, enabled: bool) -> Self {
+        self.tokenizer.enable_radical_features = enabled;
+        self
+    }
+    
+    pub fn stemming(mut self, enabled: bool) -> Self {
+        self.tokenizer.enable_stemming = enabled;
+        self
+    }
+    
+    pub fn keep_emoji(mut self, enabled: bool) -> Self {
+        self.tokenizer.keep_emoji = enabled;
+        self
+    }
+    
+    pub fn strip_urls(mut self, enabled: bool) -> Self {
+        self.tokenizer.strip_urls = enabled;
+        self
+    }
+    
+    pub fn strip_mentions(mut self, enabled: bool) -> Self {
+        self.tokenizer.strip_mentions = enabled;
+        self
+    }
+    
+    pub fn boundary_ngrams(mut self, enabled: bool) -> Self {
+        self.tokenizer.boundary_ngrams = enabled;
+        self
+    }
+    
+    pub fn collapse_repeats(mut self, enabled: bool) -> Self {
+        self.tokenizer.collapse_repeats = enabled;
+        self
+    }
+    
+    pub fn extra_separators(mut self, separators: Vec<char>) -> Self {
+        self.tokenizer.extra_separators = separators;
+        self
+    }
+    
+    // Run the configured values through the same checks as the setters
+    pub fn build(self) -> Result<JapaneseTokenizer, String> {
+        let mut tokenizer = self.tokenizer;
+        let (min_ngram, max_ngram) = (tokenizer.min_ngram, tokenizer.max_ngram);
+        tokenizer.set_ngram_range(min_ngram, max_ngram)?;
+        let min_doc_freq = tokenizer.min_doc_freq;
+        tokenizer.set_min_doc_freq(min_doc_freq)?;
+        let max_doc_freq_ratio = tokenizer.max_doc_freq_ratio;
+        tokenizer.set_max_doc_freq_ratio(max_doc_freq_ratio)?;
+        let subsample_threshold = tokenizer.subsample_threshold;
+        tokenizer.set_subsample_threshold(subsample_threshold)?;
+        let max_vocab_size = tokenizer.max_vocab_size;
+        tokenizer.set_max_vocab_size(max_vocab_size)?;
+        let max_token_chars = tokenizer.max_token_chars;
+        tokenizer.set_max_token_chars(max_token_chars)?;
+        Ok(tokenizer)
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -564,6 +1489,32 @@ mod tests {
         assert!(ngrams.contains(&"今日は".to_string()));
     }
     
+    #[test]
+    fn test_skip_grams() {
+        let mut tokenizer = JapaneseTokenizer::new();
+        assert!(!tokenizer.char_ngrams("今日は").contains(&"今は".to_string()));
+        
+        tokenizer.set_skip_gram_distance(1);
+        let ngrams = tokenizer.char_ngrams("今日は");
+        assert!(ngrams.contains(&"今は".to_string()));
+        assert!(ngrams.contains(&"今日".to_string()));
+    }
+    
+    #[test]
+    fn test_ngrams_respect_boundaries() {
+        let tokenizer = JapaneseTokenizer::new();
+        
+        for text in ["東京 大阪", "東京、大阪", "東京「大阪」", "東京！大阪"] {
+            let tokens = tokenizer.tokenize(text);
+            assert!(!tokens.contains(&"京大".to_string()), "spanning n-gram in {:?}", text);
+            assert!(tokens.contains(&"東京".to_string()));
+            assert!(tokens.contains(&"大阪".to_string()));
+        }
+        
+        // Iteration marks stay inside words
+        assert!(tokenizer.char_ngrams("人々").contains(&"人々".to_string()));
+    }
+    
     #[test]
     fn test_kanji_unigrams() {
         let tokenizer = JapaneseTokenizer::new();
@@ -593,6 +1544,200 @@ mod tests {
         assert!(sequences.contains(&"ました".to_string()));
     }
 
+    #[test]
+    fn test_latin_words() {
+        let tokenizer = JapaneseTokenizer::new();
+        
+        let tokens = tokenizer.tokenize("JavaScript勉強");
+        assert!(tokens.contains(&"JavaScript".to_string()));
+        assert!(tokens.contains(&"勉強".to_string()));
+        
+        let words = tokenizer.latin_words("HTML5とCSS3, Rust-lang");
+        assert_eq!(words, vec!["HTML5", "CSS3", "Rust", "lang"]);
+    }
+
+    #[test]
+    fn test_number_policy() {
+        let mut tokenizer = JapaneseTokenizer::new();
+        assert!(tokenizer.tokenize("2024年の予定").contains(&"24年".to_string()));
+        
+        tokenizer.set_number_policy(NumberPolicy::Normalize);
+        let tokens = tokenizer.tokenize("2024年の予定");
+        assert!(tokens.contains(&"<NUM>年".to_string()));
+        assert!(tokens.contains(&"<NUM>".to_string()));
+        assert!(!tokens.iter().any(|t| t.chars().any(|c| c.is_ascii_digit())));
+        
+        tokenizer.set_number_policy(NumberPolicy::Drop);
+        let tokens = tokenizer.tokenize("2024年の予定");
+        assert!(!tokens.iter().any(|t| t.chars().any(|c| c.is_ascii_digit())));
+        assert!(tokens.contains(&"予定".to_string()));
+    }
+
+    #[test]
+    fn test_max_token_chars() {
+        let text = "アイウエオカキクケコサシスセソタチツテト";
+        assert_eq!(text.chars().count(), 20);
+        
+        let unlimited = JapaneseTokenizer::new().tokenize(text);
+        assert!(unlimited.iter().any(|t| t.chars().count() > 5));
+        
+        let mut tokenizer = JapaneseTokenizer::builder().max_token_chars(5).build().unwrap();
+        let tokens = tokenizer.tokenize(text);
+        assert!(!tokens.is_empty());
+        assert!(tokens.iter().all(|t| t.chars().count() <= 5));
+        
+        assert!(tokenizer.set_max_token_chars(0).is_err());
+    }
+
+    #[test]
+    fn test_builder_validates_settings() {
+        assert!(JapaneseTokenizer::builder().build().is_ok());
+        assert!(JapaneseTokenizer::builder().ngram_range(0, 3).build().is_err());
+        assert!(JapaneseTokenizer::builder().ngram_range(3, 2).build().is_err());
+        assert!(JapaneseTokenizer::builder().min_doc_freq(0).build().is_err());
+        assert!(JapaneseTokenizer::builder().max_doc_freq_ratio(5.0).build().is_err());
+        assert!(JapaneseTokenizer::builder().subsample_threshold(-0.5).build().is_err());
+        assert!(JapaneseTokenizer::builder().max_vocab_size(0).build().is_err());
+        assert!(JapaneseTokenizer::builder().max_token_chars(0).build().is_err());
+    }
+
+    #[test]
+    fn test_min_token_chars() {
+        let text = "今日はいい天気";
+        let unfiltered = JapaneseTokenizer::builder().stop_words_enabled(false).build().unwrap();
+        assert!(unfiltered.tokenize(text).iter().any(|t| t.chars().count() == 1));
+        
+        let tokenizer = JapaneseTokenizer::builder()
+            .stop_words_enabled(false)
+            .min_token_chars(2)
+            .build().unwrap();
+        let tokens = tokenizer.tokenize(text);
+        assert!(!tokens.is_empty());
+        assert!(tokens.iter().all(|t| t.chars().count() >= 2));
+        assert_eq!(tokenizer.min_token_chars(), 2);
+        assert!(!tokenizer.is_compatible_with(&unfiltered));
+    }
+
+    #[test]
+    fn test_radical_features() {
+        use crate::tfidf_lsa::TfIdfLsa;
+        use crate::utils::cosine_similarity;
+        
+        let documents: Vec<String> = ["海", "河", "山"].iter().map(|s| s.to_string()).collect();
+        let similarity = |tokenizer: &JapaneseTokenizer| {
+            // embedding_dim above the vocabulary size keeps raw TF-IDF features
+            let mut model = TfIdfLsa::new(16);
+            model.fit_raw(&documents, tokenizer);
+            cosine_similarity(
+                &model.transform(&tokenizer.tokenize("海")),
+                &model.transform(&tokenizer.tokenize("河")),
+            )
+        };
+        
+        let plain = JapaneseTokenizer::new();
+        assert!(plain.radical_tokens("海").is_empty());
+        assert_eq!(similarity(&plain), 0.0);
+        
+        let tokenizer = JapaneseTokenizer::builder().radical_features(true).build().unwrap();
+        assert_eq!(tokenizer.radical_tokens("海と河"), vec!["<RAD:氵>", "<RAD:氵>"]);
+        assert!(similarity(&tokenizer) > 0.0);
+        assert!(!tokenizer.is_compatible_with(&plain));
+    }
+
+    #[test]
+    fn test_stemming() {
+        let plain = JapaneseTokenizer::new();
+        assert!(plain.stem_tokens("見ました").is_empty());
+        
+        let tokenizer = JapaneseTokenizer::builder().stemming(true).build().unwrap();
+        assert_eq!(tokenizer.stem_tokens("見ました"), vec!["見"]);
+        assert_eq!(tokenizer.stem_tokens("見る"), vec!["見"]);
+        assert_eq!(tokenizer.stem_tokens("勉強しています"), vec!["勉強"]);
+        assert!(tokenizer.stem_tokens("東京には映画が").is_empty());
+        
+        let past = tokenizer.tokenize("見ました");
+        let present = tokenizer.tokenize("見る");
+        assert!(past.contains(&"見".to_string()));
+        assert!(present.contains(&"見".to_string()));
+    }
+
+    #[test]
+    fn test_keep_emoji() {
+        let plain = JapaneseTokenizer::new();
+        assert!(!plain.tokenize("最高😊").contains(&"😊".to_string()));
+        
+        let tokenizer = JapaneseTokenizer::builder().keep_emoji(true).build().unwrap();
+        assert_eq!(tokenizer.emoji_tokens("最高😊！☀"), vec!["😊", "☀"]);
+        let tokens = tokenizer.tokenize("最高😊");
+        assert!(tokens.contains(&"😊".to_string()));
+        assert!(tokens.contains(&"最高".to_string()));
+    }
+
+    #[test]
+    fn test_strip_urls_and_mentions() {
+        let text = "@taro_99 https://example.com/a?b=1";
+        assert!(!JapaneseTokenizer::new().tokenize(text).is_empty());
+        
+        let tokenizer = JapaneseTokenizer::builder().strip_urls(true).strip_mentions(true).build().unwrap();
+        assert!(tokenizer.tokenize(text).is_empty());
+        
+        assert_eq!(
+            tokenizer.strip_urls_and_mentions("見てhttps://example.com/映画 @hanako さんへ"),
+            "見て 映画   さんへ"
+        );
+        // E-mail addresses are not mentions
+        assert_eq!(tokenizer.strip_urls_and_mentions("mail@example.com"), "mail@example.com");
+    }
+
+    #[test]
+    fn test_tokenize_debug() {
+        let tokenizer = JapaneseTokenizer::new();
+        let debug = tokenizer.tokenize_debug("映画です");
+        let find = |token: &str| debug.iter().find(|(candidate, _, _)| candidate == token).cloned();
+        
+        let (_, filtered, _) = find("です").unwrap();
+        assert!(filtered);
+        let (_, filtered, score) = find("映画").unwrap();
+        assert!(!filtered);
+        assert!(score > 0.0);
+        
+        // The unfiltered candidates are exactly the tokens
+        let mut kept: Vec<String> = debug.into_iter().filter(|(_, filtered, _)| !filtered).map(|(token, _, _)| token).collect();
+        let mut tokens = tokenizer.tokenize("映画です");
+        tokens.dedup();
+        kept.sort();
+        assert_eq!(kept, tokens);
+    }
+
+    #[test]
+    fn test_term_frequencies() {
+        let tokenizer = JapaneseTokenizer::new();
+        let frequencies = tokenizer.term_frequencies("東京都と東京湾と東京駅");
+        
+        assert_eq!(frequencies.get("東京"), Some(&3));
+        assert_eq!(frequencies.get("京湾"), Some(&1));
+        
+        let mut keys: Vec<String> = frequencies.into_keys().collect();
+        let mut tokens = tokenizer.tokenize("東京都と東京湾と東京駅");
+        keys.sort();
+        tokens.dedup();
+        assert_eq!(keys, tokens);
+    }
+
+    #[test]
+    fn test_intra_type_ngrams_only() {
+        let text = "今日映画を見た";
+        let ngrams = JapaneseTokenizer::new().char_ngrams(text);
+        assert!(ngrams.contains(&"見た".to_string()));
+        
+        let tokenizer = JapaneseTokenizer::builder().intra_type_ngrams_only(true).build().unwrap();
+        let ngrams = tokenizer.char_ngrams(text);
+        assert!(!ngrams.contains(&"見た".to_string()));
+        assert!(!ngrams.contains(&"画を".to_string()));
+        assert!(ngrams.contains(&"今日".to_string()));
+        assert!(ngrams.contains(&"今日映".to_string()));
+    }
+
     #[test]
     fn test_estimate_word_boundaries() {
         let tokenizer = JapaneseTokenizer::new();
@@ -717,6 +1862,92 @@ mod tests {
         assert!(!vocab.contains_key("です"));
     }
 
+    #[test]
+    fn test_vocabulary_threshold_setters() {
+        let mut tokenizer = JapaneseTokenizer::new();
+        tokenizer.set_min_doc_freq(2).unwrap();
+        
+        let documents = vec![
+            "今日は天気がいいですね".to_string(),
+            "明日は天気が悪いです".to_string(),
+            "今日は映画を見ました".to_string(),
+        ];
+        let vocab = tokenizer.build_vocabulary(&documents);
+        assert!(vocab.contains_key("天気"));
+        assert!(!vocab.contains_key("映画"));
+        
+        assert!(tokenizer.set_min_doc_freq(0).is_err());
+        assert!(tokenizer.set_max_doc_freq_ratio(1.5).is_err());
+        assert!(tokenizer.set_max_doc_freq_ratio(0.0).is_err());
+        assert!(tokenizer.set_max_doc_freq_ratio(0.8).is_ok());
+        assert!(tokenizer.set_max_vocab_size(0).is_err());
+        assert!(tokenizer.set_max_vocab_size(1000).is_ok());
+    }
+
+    #[test]
+    fn test_subsampling() {
+        let documents: Vec<String> = (0..10)
+            .map(|i| if i < 2 { format!("共通の特別な話{}", i) } else { format!("共通の話{}", i) })
+            .collect();
+        let mut tokenizer = JapaneseTokenizer::builder().max_doc_freq_ratio(1.0).build().unwrap();
+        let vocab = tokenizer.build_vocabulary(&documents);
+        assert!(vocab.contains_key("共通"));
+        assert!(vocab.contains_key("特別"));
+        
+        tokenizer.set_subsample_threshold(0.2).unwrap();
+        let vocab = tokenizer.build_vocabulary(&documents);
+        assert!(!vocab.contains_key("共通"));
+        assert!(vocab.contains_key("特別"));
+        assert_eq!(tokenizer.build_vocabulary(&documents), vocab);
+        
+        assert!(tokenizer.set_subsample_threshold(1.5).is_err());
+    }
+
+    #[test]
+    fn test_vocabulary_indices_are_reproducible() {
+        // Katakana bigrams without particles all share the same score
+        let documents: Vec<String> = ["アイ", "ウエ", "オカ", "キク", "ケコ"].iter().map(|s| s.to_string()).collect();
+        let tokenizer = JapaneseTokenizer::new();
+        let vocab = tokenizer.build_vocabulary(&documents);
+        let scores: HashSet<u32> = vocab
+            .keys()
+            .map(|token| tokenizer.calculate_token_score(token, 1, documents.len()).to_bits())
+            .collect();
+        assert_eq!(scores.len(), 1);
+        
+        let reversed: Vec<String> = documents.iter().rev().cloned().collect();
+        for _ in 0..5 {
+            assert_eq!(tokenizer.build_vocabulary(&documents), vocab);
+            assert_eq!(tokenizer.build_vocabulary(&reversed), vocab);
+        }
+        assert_eq!(vocab.get("アイ"), Some(&0));
+    }
+
+    #[test]
+    fn test_required_terms() {
+        let documents = vec![
+            "今日は天気がいいですね".to_string(),
+            "明日は天気が悪いです".to_string(),
+            "今日は映画を見ました".to_string(),
+        ];
+        let mut tokenizer = JapaneseTokenizer::builder()
+            .min_doc_freq(2)
+            .required_terms(vec!["映画".to_string()])
+            .build().unwrap();
+        let vocab = tokenizer.build_vocabulary(&documents);
+        assert!(vocab.contains_key("映画"));
+        assert!(vocab.contains_key("天気"));
+        assert!(!vocab.contains_key("見ま"));
+        
+        // Indices stay dense
+        let mut indices: Vec<usize> = vocab.values().copied().collect();
+        indices.sort_unstable();
+        assert_eq!(indices, (0..vocab.len()).collect::<Vec<_>>());
+        
+        tokenizer.set_required_terms(Vec::new());
+        assert!(!tokenizer.build_vocabulary(&documents).contains_key("映画"));
+    }
+
     #[test]
     fn test_stop_words_configuration() {
         let mut tokenizer = JapaneseTokenizer::new();
@@ -739,6 +1970,36 @@ mod tests {
         assert!(!tokenizer.get_stop_words().contains("は"));
     }
     
+    #[test]
+    fn test_builder() {
+        let tokenizer = JapaneseTokenizer::builder()
+            .ngram_range(2, 2)
+            .min_doc_freq(2)
+            .max_doc_freq_ratio(0.5)
+            .max_vocab_size(100)
+            .stop_words(["天気"])
+            .skip_gram_distance(1)
+            .number_policy(NumberPolicy::Drop)
+            .dictionary(vec![DictionaryEntry {
+                surface: "人工知能".to_string(),
+                variants: vec!["AI".to_string()],
+                whole_word_only: false,
+            }])
+            .build().unwrap();
+        
+        assert_eq!((tokenizer.min_ngram, tokenizer.max_ngram), (2, 2));
+        assert_eq!(tokenizer.min_doc_freq, 2);
+        assert_eq!(tokenizer.max_doc_freq_ratio, 0.5);
+        assert_eq!(tokenizer.max_vocab_size, 100);
+        assert_eq!(tokenizer.get_stop_words().len(), 1);
+        
+        let tokens = tokenizer.tokenize("AIと今日は天気2024");
+        assert!(tokens.contains(&"人工知能".to_string()));
+        assert!(tokens.contains(&"今は".to_string()));
+        assert!(!tokens.contains(&"天気".to_string()));
+        assert!(!tokens.iter().any(|t| t.chars().any(|c| c.is_ascii_digit())));
+    }
+    
     #[test]
     fn test_user_dictionary() {
         let mut tokenizer = JapaneseTokenizer::new();
@@ -748,10 +2009,12 @@ mod tests {
             DictionaryEntry {
                 surface: "人工知能".to_string(),
                 variants: vec!["AI".to_string(), "エーアイ".to_string(), "Artificial Intelligence".to_string()],
+                whole_word_only: false,
             },
             DictionaryEntry {
                 surface: "機械学習".to_string(),
                 variants: vec!["ML".to_string(), "マシンラーニング".to_string()],
+                whole_word_only: false,
             },
         ];
         
@@ -782,6 +2045,116 @@ mod tests {
         assert!(!tokens4.contains(&"人工知能".to_string()), "After clearing, AI should not be normalized");
     }
     
+    #[test]
+    fn test_whole_word_only_dictionary_entry() {
+        let entry = |whole_word_only| DictionaryEntry {
+            surface: "学".to_string(),
+            variants: vec![],
+            whole_word_only,
+        };
+        
+        let dictionary = UserDictionary::new(vec![entry(false)]);
+        assert_eq!(dictionary.find_matches("入学式"), vec![(1, 2, "学".to_string())]);
+        
+        let dictionary = UserDictionary::new(vec![entry(true)]);
+        assert!(dictionary.find_matches("入学式").is_empty());
+        assert_eq!(dictionary.find_matches("この学は"), vec![(2, 3, "学".to_string())]);
+        
+        let restored = UserDictionary::from_tsv(&dictionary.to_tsv()).unwrap();
+        assert!(restored.entries()[0].whole_word_only);
+    }
+
+    #[test]
+    fn test_dictionary_tsv_round_trip() {
+        let tsv = "# comment\n人工知能\tAI,エーアイ\n\n機械学習\tML\n東京\n";
+        let dictionary = UserDictionary::from_tsv(tsv).unwrap();
+        assert_eq!(dictionary.entries().len(), 3);
+        
+        let restored = UserDictionary::from_tsv(&dictionary.to_tsv()).unwrap();
+        assert_eq!(restored.to_tsv(), dictionary.to_tsv());
+        assert_eq!(restored.find_matches("AIとML"), dictionary.find_matches("AIとML"));
+        
+        assert!(UserDictionary::from_tsv("\tAI").is_err());
+    }
+    
+    #[test]
+    fn test_extra_separators() {
+        let plain = JapaneseTokenizer::new();
+        assert!(plain.tokenize("日本・アメリカ").iter().any(|token| token.contains('・')));
+        
+        let tokenizer = JapaneseTokenizer::builder().extra_separators(vec!['・']).build().unwrap();
+        let tokens = tokenizer.tokenize("日本・アメリカ");
+        assert!(tokens.iter().all(|token| !token.contains('・')), "{:?}", tokens);
+        assert!(tokens.contains(&"日本".to_string()));
+        assert!(tokens.contains(&"アメリカ".to_string()));
+    }
+
+    #[test]
+    fn test_collapse_repeats() {
+        let plain = JapaneseTokenizer::new();
+        assert_ne!(plain.tokenize("すごーーーい"), plain.tokenize("すごーい"));
+        
+        let tokenizer = JapaneseTokenizer::builder().collapse_repeats(true).build().unwrap();
+        assert_eq!(tokenizer.tokenize("すごーーーい"), tokenizer.tokenize("すごーい"));
+        assert_eq!(tokenizer.tokenize("草wwwww"), tokenizer.tokenize("草ww"));
+        assert_eq!(tokenizer.collapse_repeated_chars("母はははと笑った〜〜"), "母ははと笑った〜");
+    }
+
+    #[test]
+    fn test_boundary_ngrams() {
+        let entries = vec![DictionaryEntry {
+            surface: "人工知能".to_string(),
+            variants: vec![],
+            whole_word_only: false,
+        }];
+        let text = "最新人工知能技術";
+        
+        let plain = JapaneseTokenizer::builder().dictionary(entries.clone()).build().unwrap();
+        let tokens = plain.tokenize(text);
+        assert!(tokens.contains(&"人工知能".to_string()));
+        assert!(!tokens.contains(&"新人".to_string()));
+        assert!(!tokens.contains(&"能技".to_string()));
+        
+        let tokenizer = JapaneseTokenizer::builder().dictionary(entries).boundary_ngrams(true).build().unwrap();
+        let tokens = tokenizer.tokenize(text);
+        assert!(tokens.contains(&"人工知能".to_string()));
+        for gram in ["新人", "最新人", "新人工", "能技", "知能技", "能技術"] {
+            assert!(tokens.contains(&gram.to_string()), "{}", gram);
+        }
+        // Only n-grams crossing a junction are added
+        assert!(!tokens.contains(&"工知".to_string()));
+    }
+
+    #[test]
+    fn test_dictionary_order_restored_after_deserialize() {
+        let dictionary = UserDictionary::from_tsv("東京\n東京都\tトウキョウト\n").unwrap();
+        let expected = dictionary.find_matches("東京都庁とトウキョウト");
+        assert_eq!(expected[0].2, "東京都");
+        
+        let json = serde_json::to_string(&dictionary).unwrap();
+        let restored: UserDictionary = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.find_matches("東京都庁とトウキョウト"), expected);
+        
+        // Payloads with entries out of order (e.g. edited by hand) match the same
+        let mut payload: serde_json::Value = serde_json::from_str(&json).unwrap();
+        payload["entries"].as_array_mut().unwrap().reverse();
+        payload["variant_to_surface"] = serde_json::json!({});
+        let reordered: UserDictionary = serde_json::from_value(payload).unwrap();
+        assert_eq!(reordered.find_matches("東京都庁とトウキョウト"), expected);
+        assert_eq!(reordered.terms(), dictionary.terms());
+    }
+    
+    #[test]
+    fn test_load_stop_words_from_text() {
+        let mut tokenizer = JapaneseTokenizer::new();
+        tokenizer.load_stop_words_from_text("天気\n  映画  \n\n# 今日\n");
+        
+        assert!(tokenizer.get_stop_words().contains("天気"));
+        assert!(tokenizer.get_stop_words().contains("映画"));
+        assert!(!tokenizer.get_stop_words().contains("# 今日"));
+        assert!(!tokenizer.tokenize("今日は天気").contains(&"天気".to_string()));
+    }
+    
     #[test]
     fn test_dictionary_score_boost() {
         let mut tokenizer = JapaneseTokenizer::new();
@@ -790,6 +2163,7 @@ mod tests {
             DictionaryEntry {
                 surface: "人工知能".to_string(),
                 variants: vec!["AI".to_string()],
+                whole_word_only: false,
             },
         ];
         
@@ -1,3 +1,7 @@
+use serde::{Deserialize, Serialize};
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::prelude::*;
+
 // Set panic hook for better error messages in browser
 pub fn set_panic_hook() {
     // When the `console_error_panic_hook` feature is enabled, we can call the
@@ -9,6 +13,17 @@ pub fn set_panic_hook() {
 
 // Cosine similarity calculation
 pub fn cosine_similarity(vec1: &[f32], vec2: &[f32]) -> f32 {
+    #[cfg(feature = "simd")]
+    {
+        cosine_similarity_lanes(vec1, vec2)
+    }
+    #[cfg(not(feature = "simd"))]
+    {
+        cosine_similarity_scalar(vec1, vec2)
+    }
+}
+
+fn cosine_similarity_scalar(vec1: &[f32], vec2: &[f32]) -> f32 {
     if vec1.len() != vec2.len() {
         return 0.0;
     }
@@ -23,11 +38,55 @@ pub fn cosine_similarity(vec1: &[f32], vec2: &[f32]) -> f32 {
         norm2 += vec2[i] * vec2[i];
     }
     
+    finish_cosine(dot_product, norm1, norm2)
+}
+
+// Accumulate LANES independent partial sums so the compiler can vectorize the
+// loop; the remainder that doesn't fill a lane group is handled as scalars
+#[cfg(any(feature = "simd", test))]
+const LANES: usize = 8;
+
+#[cfg(any(feature = "simd", test))]
+fn cosine_similarity_lanes(vec1: &[f32], vec2: &[f32]) -> f32 {
+    if vec1.len() != vec2.len() || vec1.len() < LANES {
+        return cosine_similarity_scalar(vec1, vec2);
+    }
+    
+    let mut dot_product = [0.0f32; LANES];
+    let mut norm1 = [0.0f32; LANES];
+    let mut norm2 = [0.0f32; LANES];
+    
+    let chunks1 = vec1.chunks_exact(LANES);
+    let chunks2 = vec2.chunks_exact(LANES);
+    let (tail1, tail2) = (chunks1.remainder(), chunks2.remainder());
+    
+    for (a, b) in chunks1.zip(chunks2) {
+        for lane in 0..LANES {
+            dot_product[lane] += a[lane] * b[lane];
+            norm1[lane] += a[lane] * a[lane];
+            norm2[lane] += b[lane] * b[lane];
+        }
+    }
+    
+    let mut dot_product: f32 = dot_product.iter().sum();
+    let mut norm1: f32 = norm1.iter().sum();
+    let mut norm2: f32 = norm2.iter().sum();
+    for (a, b) in tail1.iter().zip(tail2) {
+        dot_product += a * b;
+        norm1 += a * a;
+        norm2 += b * b;
+    }
+    
+    finish_cosine(dot_product, norm1, norm2)
+}
+
+fn finish_cosine(dot_product: f32, norm1: f32, norm2: f32) -> f32 {
     if norm1 == 0.0 || norm2 == 0.0 {
         return 0.0;
     }
     
-    dot_product / (norm1.sqrt() * norm2.sqrt())
+    // Rounding can push the ratio slightly outside [-1, 1]
+    (dot_product / (norm1.sqrt() * norm2.sqrt())).clamp(-1.0, 1.0)
 }
 
 // L2 normalization
@@ -40,6 +99,101 @@ pub fn l2_normalize(vec: &mut [f32]) {
     }
 }
 
+// L1 normalization: absolute values sum to 1.0 (zero vectors are left as is)
+pub fn l1_normalize(vec: &mut [f32]) {
+    let norm: f32 = vec.iter().map(|x| x.abs()).sum();
+    if norm > 0.0 {
+        for v in vec.iter_mut() {
+            *v /= norm;
+        }
+    }
+}
+
+// How embedders scale their output vectors. Cosine similarity is unaffected by
+// the choice, but distances and dot products between embeddings are not.
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum Normalization {
+    // Unit Euclidean length
+    #[default]
+    L2,
+    // Absolute values sum to 1.0
+    L1,
+}
+
+pub fn normalize(vec: &mut [f32], normalization: Normalization) {
+    match normalization {
+        Normalization::L2 => l2_normalize(vec),
+        Normalization::L1 => l1_normalize(vec),
+    }
+}
+
+// 64-bit FNV-1a over a sequence of byte slices. Unlike DefaultHasher its output
+// is fixed by definition, so it is stable across Rust versions and platforms.
+pub fn fnv1a_64(parts: &[&[u8]]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+    
+    let mut hash = OFFSET_BASIS;
+    for part in parts {
+        for &byte in *part {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(PRIME);
+        }
+    }
+    hash
+}
+
+// Small deterministic PRNG (SplitMix64) for reproducible seeding
+#[derive(Debug, Clone)]
+pub struct SeededRng {
+    state: u64,
+}
+
+impl SeededRng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+    
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+    
+    // Uniform value in [0, 1)
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+// Check whether a vector is the all-zero embedding returned for empty input
+pub fn is_zero_vector(vec: &[f32]) -> bool {
+    vec.iter().all(|&v| v == 0.0)
+}
+
+// Version of the JSON layout written by export_model/to_json. Bump it when a
+// change would make older payloads deserialize incorrectly.
+pub(crate) const FORMAT_VERSION: u32 = 1;
+
+// Payloads written before versioning was introduced are version 1
+pub(crate) fn default_format_version() -> u32 {
+    1
+}
+
+#[derive(Deserialize)]
+struct FormatVersionProbe {
+    #[serde(default = "default_format_version")]
+    format_version: u32,
+}
+
+// Read only the format_version of a serialized model, ignoring every other field
+pub(crate) fn read_format_version(json: &str) -> Result<u32, serde_json::Error> {
+    serde_json::from_str::<FormatVersionProbe>(json).map(|probe| probe.format_version)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -55,6 +209,30 @@ mod tests {
         assert!((cosine_similarity(&vec3, &vec4) - 0.0).abs() < 1e-6);
     }
     
+    #[test]
+    fn test_cosine_similarity_is_clamped() {
+        // Unclamped, this evaluates to 1.0000001 in f32
+        let vec = vec![0.38286334, 0.7954477, 0.5005112];
+        assert_eq!(cosine_similarity(&vec, &vec), 1.0);
+        
+        let negated: Vec<f32> = vec.iter().map(|x| -x).collect();
+        assert_eq!(cosine_similarity(&vec, &negated), -1.0);
+    }
+    
+    #[test]
+    fn test_lane_cosine_matches_scalar() {
+        let mut rng = SeededRng::new(7);
+        for len in [512, 515, 5] {
+            let vec1: Vec<f32> = (0..len).map(|_| rng.next_f32() - 0.5).collect();
+            let vec2: Vec<f32> = (0..len).map(|_| rng.next_f32() - 0.5).collect();
+            
+            let scalar = cosine_similarity_scalar(&vec1, &vec2);
+            let lanes = cosine_similarity_lanes(&vec1, &vec2);
+            assert!((scalar - lanes).abs() < 1e-5, "{} vs {}", scalar, lanes);
+            assert!((cosine_similarity(&vec1, &vec2) - scalar).abs() < 1e-5);
+        }
+    }
+    
     #[test]
     fn test_l2_normalize() {
         let mut vec = vec![3.0, 4.0];
@@ -62,4 +240,43 @@ mod tests {
         assert!((vec[0] - 0.6).abs() < 1e-6);
         assert!((vec[1] - 0.8).abs() < 1e-6);
     }
+    
+    #[test]
+    fn test_l1_normalize() {
+        let mut vec = vec![3.0, -4.0, 1.0];
+        normalize(&mut vec, Normalization::L1);
+        assert!((vec.iter().map(|x| x.abs()).sum::<f32>() - 1.0).abs() < 1e-6);
+        assert!((vec[1] + 0.5).abs() < 1e-6);
+        
+        let mut zero = vec![0.0, 0.0];
+        l1_normalize(&mut zero);
+        assert_eq!(zero, vec![0.0, 0.0]);
+    }
+    
+    #[test]
+    fn test_fnv1a_64() {
+        // Reference values from the FNV specification
+        assert_eq!(fnv1a_64(&[b""]), 0xcbf29ce484222325);
+        assert_eq!(fnv1a_64(&[b"a"]), 0xaf63dc4c8601ec8c);
+        assert_eq!(fnv1a_64(&[b"foo", b"bar"]), fnv1a_64(&[b"foobar"]));
+    }
+    
+    #[test]
+    fn test_seeded_rng_is_deterministic() {
+        let mut rng1 = SeededRng::new(42);
+        let mut rng2 = SeededRng::new(42);
+        for _ in 0..10 {
+            assert_eq!(rng1.next_u64(), rng2.next_u64());
+            let value = rng1.next_f32();
+            assert_eq!(value, rng2.next_f32());
+            assert!((0.0..1.0).contains(&value));
+        }
+    }
+    
+    #[test]
+    fn test_is_zero_vector() {
+        assert!(is_zero_vector(&[0.0, 0.0, 0.0]));
+        assert!(is_zero_vector(&[]));
+        assert!(!is_zero_vector(&[0.0, 0.1, 0.0]));
+    }
 }
\ No newline at end of file